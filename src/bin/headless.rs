@@ -0,0 +1,129 @@
+// Runs a scene without a window or a serial device and prints the final body states as JSON.
+// Each step is one fixed physics step (1/60 s).
+//
+// Usage: headless <scene.json> [--steps N] [--input script.csv] [--out states.json] [--tilt-gravity]
+//                 [--broadphase grid|sap|tree|brute]
+//
+// The input script uses the Pico's CSV format ("x,y,z" per line, one line per step).
+// When it runs out, the last line is held. Without a script the input stays at rest.
+// Input tilts the kinematic bodies from how the scene placed them, or gravity itself
+// with --tilt-gravity.
+// --broadphase picks how candidate pairs are found (uniform grid by default).
+
+use std::error::Error;
+use std::fs;
+
+use physics_engine::broadphase::{Broadphase, BruteForce, DynamicAabbTree, SweepAndPrune, UniformGrid};
+use physics_engine::collision::FIXED_TIMESTEP;
+use physics_engine::controller::Input;
+use physics_engine::things::Dynamics;
+use physics_engine::{loader, GravityMode, Id, World};
+
+const DEFAULT_STEPS: u32 = 600;
+
+struct Args {
+    scene: String,
+    steps: u32,
+    input: Option<String>,
+    out: Option<String>,
+    tilt_gravity: bool,
+    broadphase: Box<dyn Broadphase>,
+}
+
+fn parse_args() -> Result<Args, Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let mut scene = None;
+    let mut steps = DEFAULT_STEPS;
+    let mut input = None;
+    let mut out = None;
+    let mut tilt_gravity = false;
+    let mut broadphase: Box<dyn Broadphase> = Box::new(UniformGrid::default());
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => steps = args.next().ok_or("--steps needs a value")?.parse()?,
+            "--input" => input = Some(args.next().ok_or("--input needs a path")?),
+            "--out" => out = Some(args.next().ok_or("--out needs a path")?),
+            "--tilt-gravity" => tilt_gravity = true,
+            "--broadphase" => broadphase = match args.next().ok_or("--broadphase needs a name")?.as_str() {
+                "grid" => Box::new(UniformGrid::default()),
+                "sap" => Box::new(SweepAndPrune::default()),
+                "tree" => Box::new(DynamicAabbTree::default()),
+                "brute" => Box::new(BruteForce),
+                name => return Err(format!("Unknown broadphase: {}", name).into()),
+            },
+            _ if scene.is_none() => scene = Some(arg),
+            _ => return Err(format!("Unexpected argument: {}", arg).into()),
+        }
+    }
+
+    Ok(Args {
+        scene: scene.ok_or("Usage: headless <scene.json> [--steps N] [--input script.csv] [--out states.json] [--tilt-gravity] [--broadphase grid|sap|tree|brute]")?,
+        steps,
+        input,
+        out,
+        tilt_gravity,
+        broadphase,
+    })
+}
+
+fn load_script(path: &str) -> Result<Vec<Input>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    let script = contents.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Input::from_csv_line(line).ok_or(format!("Bad input line: {}", line)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(script)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+
+    let mut world = World::new();
+    world.set_broadphase(args.broadphase);
+    let ids = loader::load_scene(&args.scene, &mut world)?;
+    // Kinematic bodies and how the scene turned them, which the tilt adds to
+    let kinematic: Vec<(Id, f32)> = ids.into_iter()
+        .filter_map(|id| {
+            let shape = world.shape(id)?;
+            matches!(shape.dynamics, Dynamics::Kinematic).then_some((id, shape.transform.rotation))
+        })
+        .collect();
+
+    if args.tilt_gravity {
+        world.set_gravity_mode(GravityMode::Tilt);
+    }
+
+    let script = match &args.input {
+        Some(path) => load_script(path)?,
+        None => Vec::new(),
+    };
+
+    for step in 0..args.steps as usize {
+        if let Some(input) = script.get(step).or(script.last()) {
+            world.apply_input(input);
+
+            // Otherwise scripted input tilts the kinematic bodies, turning them
+            // so things on them are carried along
+            if world.gravity_mode() == GravityMode::Fixed {
+                for &(id, rotation) in &kinematic {
+                    let transform = &world.shape(id).unwrap().transform;
+                    let (x_pos, y_pos) = (transform.x_pos, transform.y_pos);
+                    world.set_target_pose(id, x_pos, y_pos, rotation + input.tilt_degrees());
+                }
+            }
+        }
+
+        world.step(FIXED_TIMESTEP);
+    }
+
+    let states = serde_json::to_string_pretty(&world.snapshot())?;
+
+    match &args.out {
+        Some(path) => fs::write(path, states)?,
+        None => println!("{}", states),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,530 @@
+//! Broadphase: cheaply finds the pairs of shapes that are close enough to need
+//! a proper (narrowphase) collision check
+
+use std::collections::{HashMap, HashSet};
+
+use crate::collision::CONTACT_SKIN;
+use crate::things::Shape;
+use crate::Id;
+
+/// Default grid cell size (px), a bit over the demo's balls.
+pub const DEFAULT_CELL_SIZE: f32 = 32.0;
+
+// Shapes covering more cells than this (long platforms, terrain chains) are kept
+// out of the grid and checked against everything instead
+const MAX_CELLS_PER_SHAPE: i64 = 256;
+
+// How far (px) tree boxes reach past their shape, so small moves don't need a reinsert
+const TREE_MARGIN: f32 = 8.0;
+
+type Aabb = (f32, f32, f32, f32); // min_x, min_y, max_x, max_y
+type Cell = (i32, i32);
+
+/// Strategy for picking the pairs of shapes worth a narrowphase check.
+/// Pick one per world with [`World::set_broadphase`](crate::World::set_broadphase).
+pub trait Broadphase {
+    /// Every pair of shapes whose AABBs, grown by the contact skin, might
+    /// overlap, each pair once.
+    /// Extra pairs only cost time, missing pairs are missed collisions.
+    fn pairs(&mut self, shapes: &HashMap<Id, Shape>) -> Vec<(Id, Id)>;
+}
+
+// A shape's AABB grown by the contact skin, so faces that are close but not
+// quite touching still reach the narrowphase
+fn bounds(shape: &Shape) -> Aabb {
+    let (min_x, min_y, max_x, max_y) = shape.aabb();
+    (min_x - CONTACT_SKIN, min_y - CONTACT_SKIN, max_x + CONTACT_SKIN, max_y + CONTACT_SKIN)
+}
+
+// Finite and the right way round
+fn valid(aabb: Aabb) -> bool {
+    let (min_x, min_y, max_x, max_y) = aabb;
+    min_x <= max_x && min_y <= max_y && [min_x, min_y, max_x, max_y].iter().all(|v| v.is_finite())
+}
+
+fn overlaps(a: Aabb, b: Aabb) -> bool {
+    a.0 <= b.2 && b.0 <= a.2 && a.1 <= b.3 && b.1 <= a.3
+}
+
+// Lower id first, so the same pair always looks the same
+fn ordered(id_a: Id, id_b: Id) -> (Id, Id) {
+    (id_a.min(id_b), id_a.max(id_b))
+}
+
+/// Every pair of shapes, no filtering at all. Fine for a handful of shapes.
+#[derive(Default)]
+pub struct BruteForce;
+
+impl Broadphase for BruteForce {
+    fn pairs(&mut self, shapes: &HashMap<Id, Shape>) -> Vec<(Id, Id)> {
+        let ids: Vec<_> = shapes.keys().copied().collect();
+
+        let mut pairs = Vec::new();
+        for i in 0..ids.len() {
+            for j in i+1..ids.len() {
+                pairs.push(ordered(ids[i], ids[j]));
+            }
+        }
+
+        pairs
+    }
+}
+
+/// Buckets shapes by the grid cells their AABB touches. Only shapes sharing a cell
+/// become a pair. Works best when most shapes are about one cell in size.
+pub struct UniformGrid {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<(Id, Cell, Aabb)>>, // Shapes touching each cell, with their first cell
+    large: Vec<Id>,
+}
+
+impl Default for UniformGrid {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl UniformGrid {
+    /// A grid of `cell_size` px cells, or [`DEFAULT_CELL_SIZE`] ones if that isn't positive
+    pub fn new(cell_size: f32) -> Self {
+        let mut grid = UniformGrid {
+            cell_size: DEFAULT_CELL_SIZE,
+            cells: HashMap::new(),
+            large: Vec::new(),
+        };
+        grid.set_cell_size(cell_size);
+
+        grid
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Ignores sizes that aren't positive
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        if cell_size > 0.0 {
+            self.cell_size = cell_size;
+        }
+    }
+
+    fn cell(&self, x: f32, y: f32) -> Cell {
+        ((x / self.cell_size).floor() as i32, (y / self.cell_size).floor() as i32)
+    }
+}
+
+impl Broadphase for UniformGrid {
+    fn pairs(&mut self, shapes: &HashMap<Id, Shape>) -> Vec<(Id, Id)> {
+        self.cells.clear();
+        self.large.clear();
+
+        for (&id, shape) in shapes {
+            let aabb = bounds(shape);
+            let (min_x, min_y, max_x, max_y) = aabb;
+            let (min_cell_x, min_cell_y) = self.cell(min_x, min_y);
+            let (max_cell_x, max_cell_y) = self.cell(max_x, max_y);
+
+            // Inverted or non-finite boxes have no sensible cells either, pair them with everything
+            let cell_count = (max_cell_x as i64 - min_cell_x as i64 + 1)
+                .saturating_mul(max_cell_y as i64 - min_cell_y as i64 + 1);
+            if !valid(aabb) || cell_count > MAX_CELLS_PER_SHAPE {
+                self.large.push(id);
+                continue;
+            }
+
+            for cell_x in min_cell_x..=max_cell_x {
+                for cell_y in min_cell_y..=max_cell_y {
+                    self.cells.entry((cell_x, cell_y)).or_default().push((id, (min_cell_x, min_cell_y), aabb));
+                }
+            }
+        }
+
+        let mut pairs = Vec::new();
+        for (&cell, entries) in &self.cells {
+            for i in 0..entries.len() {
+                for j in i+1..entries.len() {
+                    let (id_a, first_a, aabb_a) = entries[i];
+                    let (id_b, first_b, aabb_b) = entries[j];
+
+                    // Shapes sharing several cells only pair up in the first of them
+                    if (first_a.0.max(first_b.0), first_a.1.max(first_b.1)) == cell && overlaps(aabb_a, aabb_b) {
+                        pairs.push(ordered(id_a, id_b));
+                    }
+                }
+            }
+        }
+
+        for &large_id in &self.large {
+            let large_aabb = bounds(&shapes[&large_id]);
+            for (&id, shape) in shapes {
+                // Two large shapes pair up once, from the first of them
+                if id == large_id || (self.large.contains(&id) && id < large_id) {
+                    continue;
+                }
+
+                let aabb = bounds(shape);
+                if !(valid(large_aabb) && valid(aabb)) || overlaps(large_aabb, aabb) {
+                    pairs.push(ordered(large_id, id));
+                }
+            }
+        }
+
+        pairs
+    }
+}
+
+/// Sorts shapes along x and only pairs those whose x ranges overlap (then checks y).
+/// The order is kept between steps, so it's nearly sorted already and re-sorting is cheap.
+/// Good for shapes of mixed sizes that mostly spread out sideways.
+#[derive(Default)]
+pub struct SweepAndPrune {
+    entries: Vec<(Id, Aabb)>,
+}
+
+impl Broadphase for SweepAndPrune {
+    fn pairs(&mut self, shapes: &HashMap<Id, Shape>) -> Vec<(Id, Id)> {
+        // Refresh the boxes, dropping removed shapes and adding new ones at the end
+        self.entries.retain(|(id, _)| shapes.contains_key(id));
+        for (id, aabb) in self.entries.iter_mut() {
+            *aabb = bounds(&shapes[id]);
+        }
+        if self.entries.len() < shapes.len() {
+            let known: HashSet<Id> = self.entries.iter().map(|&(id, _)| id).collect();
+            for (&id, shape) in shapes {
+                if !known.contains(&id) {
+                    self.entries.push((id, bounds(shape)));
+                }
+            }
+        }
+
+        // Nearly sorted already, which the standard sort gets through in about linear time
+        self.entries.sort_by(|(_, a), (_, b)| a.0.total_cmp(&b.0));
+
+        let mut pairs = Vec::new();
+        for i in 0..self.entries.len() {
+            let (id_a, aabb_a) = self.entries[i];
+            for &(id_b, aabb_b) in &self.entries[i + 1..] {
+                // Everything further along starts past this box
+                if aabb_b.0 > aabb_a.2 {
+                    break;
+                }
+                if aabb_a.1 <= aabb_b.3 && aabb_b.1 <= aabb_a.3 {
+                    pairs.push(ordered(id_a, id_b));
+                }
+            }
+        }
+
+        pairs
+    }
+}
+
+struct TreeNode {
+    aabb: Aabb,
+    parent: Option<usize>,
+    children: Option<(usize, usize)>, // None for leaves
+    id: Option<Id>, // Set for leaves
+}
+
+/// Binary tree of boxes, each parent box holding both children. Leaves hold a shape's
+/// box grown by a margin, and only get reinserted once the shape leaves it.
+/// Good for a few huge shapes (terrain, walls) among many small ones.
+#[derive(Default)]
+pub struct DynamicAabbTree {
+    nodes: Vec<TreeNode>,
+    free: Vec<usize>, // Unused node slots
+    root: Option<usize>,
+    leaves: HashMap<Id, usize>,
+}
+
+fn union(a: Aabb, b: Aabb) -> Aabb {
+    (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))
+}
+
+fn contains(outer: Aabb, inner: Aabb) -> bool {
+    outer.0 <= inner.0 && outer.1 <= inner.1 && outer.2 >= inner.2 && outer.3 >= inner.3
+}
+
+fn perimeter(aabb: Aabb) -> f32 {
+    2.0 * ((aabb.2 - aabb.0) + (aabb.3 - aabb.1))
+}
+
+impl DynamicAabbTree {
+    fn allocate(&mut self, node: TreeNode) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn insert(&mut self, id: Id, aabb: Aabb) {
+        let fat = (aabb.0 - TREE_MARGIN, aabb.1 - TREE_MARGIN, aabb.2 + TREE_MARGIN, aabb.3 + TREE_MARGIN);
+        let leaf = self.allocate(TreeNode { aabb: fat, parent: None, children: None, id: Some(id) });
+        self.leaves.insert(id, leaf);
+
+        let Some(root) = self.root else {
+            self.root = Some(leaf);
+            return;
+        };
+
+        // Walk down towards the sibling that grows the tree's boxes the least
+        let mut sibling = root;
+        while let Some((left, right)) = self.nodes[sibling].children {
+            let cost_here = perimeter(union(self.nodes[sibling].aabb, fat));
+            // Going further down still grows this node's box
+            let inherited = cost_here - perimeter(self.nodes[sibling].aabb);
+
+            let cost = |child: usize| {
+                let grown = perimeter(union(self.nodes[child].aabb, fat));
+                match self.nodes[child].children {
+                    None => grown + inherited,
+                    Some(_) => grown - perimeter(self.nodes[child].aabb) + inherited,
+                }
+            };
+            let (cost_left, cost_right) = (cost(left), cost(right));
+
+            if cost_here < cost_left && cost_here < cost_right {
+                break;
+            }
+            sibling = if cost_left < cost_right { left } else { right };
+        }
+
+        // New parent joins the sibling and the leaf
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate(TreeNode {
+            aabb: union(self.nodes[sibling].aabb, fat),
+            parent: old_parent,
+            children: Some((sibling, leaf)),
+            id: None,
+        });
+        self.nodes[sibling].parent = Some(parent);
+        self.nodes[leaf].parent = Some(parent);
+
+        match old_parent {
+            Some(old_parent) => {
+                self.replace_child(old_parent, sibling, parent);
+                self.refit(Some(old_parent));
+            }
+            None => self.root = Some(parent),
+        }
+    }
+
+    fn remove(&mut self, id: Id) {
+        let Some(leaf) = self.leaves.remove(&id) else {
+            return;
+        };
+        self.free.push(leaf);
+
+        let Some(parent) = self.nodes[leaf].parent else {
+            self.root = None;
+            return;
+        };
+
+        // The sibling takes the parent's place
+        let (left, right) = self.nodes[parent].children.unwrap();
+        let sibling = if left == leaf { right } else { left };
+        let grandparent = self.nodes[parent].parent;
+        self.nodes[sibling].parent = grandparent;
+        self.free.push(parent);
+
+        match grandparent {
+            Some(grandparent) => {
+                self.replace_child(grandparent, parent, sibling);
+                self.refit(Some(grandparent));
+            }
+            None => self.root = Some(sibling),
+        }
+    }
+
+    fn replace_child(&mut self, parent: usize, old_child: usize, new_child: usize) {
+        let (left, right) = self.nodes[parent].children.unwrap();
+        self.nodes[parent].children = Some(if left == old_child { (new_child, right) } else { (left, new_child) });
+    }
+
+    // Shrinks or grows the boxes from `node` up to the root
+    fn refit(&mut self, mut node: Option<usize>) {
+        while let Some(index) = node {
+            let (left, right) = self.nodes[index].children.unwrap();
+            self.nodes[index].aabb = union(self.nodes[left].aabb, self.nodes[right].aabb);
+            node = self.nodes[index].parent;
+        }
+    }
+}
+
+impl Broadphase for DynamicAabbTree {
+    fn pairs(&mut self, shapes: &HashMap<Id, Shape>) -> Vec<(Id, Id)> {
+        let removed: Vec<_> = self.leaves.keys().filter(|id| !shapes.contains_key(id)).copied().collect();
+        for id in removed {
+            self.remove(id);
+        }
+
+        for (&id, shape) in shapes {
+            let aabb = bounds(shape);
+            match self.leaves.get(&id) {
+                Some(&leaf) if contains(self.nodes[leaf].aabb, aabb) => {}
+                Some(_) => {
+                    self.remove(id);
+                    self.insert(id, aabb);
+                }
+                None => self.insert(id, aabb),
+            }
+        }
+
+        let mut pairs = Vec::new();
+        let mut stack = Vec::new();
+        for &id in self.leaves.keys() {
+            // The shape's own box, the padding in the tree would only add pairs
+            // that don't really overlap
+            let aabb = bounds(&shapes[&id]);
+
+            stack.extend(self.root);
+            while let Some(index) = stack.pop() {
+                let node = &self.nodes[index];
+                if !overlaps(node.aabb, aabb) {
+                    continue;
+                }
+
+                match (node.children, node.id) {
+                    (Some((left, right)), _) => stack.extend([left, right]),
+                    // Both leaves find each other, keep it from the lower id's side
+                    (None, Some(other)) if other > id && overlaps(bounds(&shapes[&other]), aabb) => pairs.push((id, other)),
+                    _ => {}
+                }
+            }
+        }
+
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::things::{CollisionType, Dynamics, Transform};
+
+    fn circle(x_pos: f32, y_pos: f32) -> Shape {
+        Shape::new(Transform::new(x_pos, y_pos, 20, 20, 0.0), CollisionType::Circle, Dynamics::Dynamic, 0.0)
+    }
+
+    #[test]
+    fn grid_pairs_only_nearby_shapes() {
+        let shapes = HashMap::from([(0, circle(0.0, 0.0)), (1, circle(15.0, 0.0)), (2, circle(500.0, 0.0))]);
+        assert_eq!(UniformGrid::default().pairs(&shapes), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn grid_survives_inverted_and_infinite_boxes() {
+        let mut shapes = HashMap::from([(0, circle(0.0, 0.0)), (1, circle(15.0, 0.0))]);
+        // An empty polygon's box runs from +inf to -inf
+        shapes.insert(2, Shape::new(Transform::new(0.0, 0.0, 0, 0, 0.0), CollisionType::Polygon(vec![]), Dynamics::Kinematic, 0.0));
+        shapes.insert(3, circle(f32::INFINITY, 0.0));
+
+        let mut pairs = UniformGrid::default().pairs(&shapes);
+        pairs.sort();
+        // Both odd shapes pair with everything, the circles with each other
+        assert_eq!(pairs, vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn shapes_just_apart_within_the_skin_still_pair() {
+        // 0.4 px gap between the two circles
+        let shapes = HashMap::from([(0, circle(0.0, 0.0)), (1, circle(20.4, 0.0)), (2, circle(22.0, 30.0))]);
+        let broadphases: [Box<dyn Broadphase>; 3] = [
+            Box::new(UniformGrid::default()),
+            Box::new(SweepAndPrune::default()),
+            Box::new(DynamicAabbTree::default()),
+        ];
+        for mut broadphase in broadphases {
+            assert_eq!(broadphase.pairs(&shapes), vec![(0, 1)]);
+        }
+    }
+
+    // Small deterministic generator, so a failing scene can be replayed
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f32 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+
+        fn range(&mut self, low: f32, high: f32) -> f32 {
+            low + (high - low) * self.next()
+        }
+    }
+
+    fn random_shape(rng: &mut Lcg) -> Shape {
+        let transform = |rng: &mut Lcg, width: f32, height: f32| {
+            Transform::new(rng.range(0.0, 1000.0), rng.range(0.0, 1000.0), width as i32, height as i32, rng.range(0.0, 360.0))
+        };
+        match (rng.next() * 10.0) as u32 {
+            // Now and then something the grid keeps out of its cells
+            0 => Shape::new(transform(rng, 900.0, 700.0), CollisionType::Rectangle, Dynamics::Kinematic, 0.0),
+            1..=4 => {
+                let size = rng.range(4.0, 60.0);
+                Shape::new(transform(rng, size, size), CollisionType::Circle, Dynamics::Dynamic, 0.0)
+            }
+            _ => {
+                let (width, height) = (rng.range(4.0, 120.0), rng.range(4.0, 40.0));
+                Shape::new(transform(rng, width, height), CollisionType::Rectangle, Dynamics::Dynamic, 0.0)
+            }
+        }
+    }
+
+    fn sorted(mut pairs: Vec<(Id, Id)>) -> Vec<(Id, Id)> {
+        pairs.sort_unstable();
+        pairs
+    }
+
+    #[test]
+    fn broadphases_agree_with_brute_force_as_shapes_move() {
+        let mut rng = Lcg(7);
+        let mut shapes: HashMap<Id, Shape> = (0..150).map(|id| (id, random_shape(&mut rng))).collect();
+        let mut next_id = shapes.len() as Id;
+
+        // Kept across rounds, so the SAP order and the tree have to keep up
+        let mut broadphases: [(&str, Box<dyn Broadphase>); 3] = [
+            ("grid", Box::new(UniformGrid::default())),
+            ("sap", Box::new(SweepAndPrune::default())),
+            ("tree", Box::new(DynamicAabbTree::default())),
+        ];
+
+        for round in 0..40 {
+            let expected = sorted(
+                BruteForce.pairs(&shapes).into_iter()
+                    .filter(|(id_a, id_b)| overlaps(bounds(&shapes[id_a]), bounds(&shapes[id_b])))
+                    .collect(),
+            );
+            for (name, broadphase) in broadphases.iter_mut() {
+                assert_eq!(sorted(broadphase.pairs(&shapes)), expected, "{} in round {}", name, round);
+            }
+
+            // Nudge most shapes, throw a few across the scene, swap some out for new ones
+            let ids: Vec<Id> = shapes.keys().copied().collect();
+            for id in ids {
+                let roll = rng.next();
+                if roll < 0.05 {
+                    shapes.remove(&id);
+                    shapes.insert(next_id, random_shape(&mut rng));
+                    next_id += 1;
+                    continue;
+                }
+
+                let transform = &mut shapes.get_mut(&id).unwrap().transform;
+                if roll < 0.15 {
+                    transform.x_pos = rng.range(0.0, 1000.0);
+                    transform.y_pos = rng.range(0.0, 1000.0);
+                } else {
+                    transform.x_pos += rng.range(-6.0, 6.0);
+                    transform.y_pos += rng.range(-6.0, 6.0);
+                    transform.rotation += rng.range(-10.0, 10.0);
+                }
+            }
+        }
+    }
+}
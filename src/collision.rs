@@ -1,18 +1,133 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::atomic::{AtomicU16, Ordering};
 use crate::{things, Id};
-use crate::things::{CollisionType, Dynamics, Shape, Sprite, Thing};
+use crate::broadphase::{Broadphase, UniformGrid};
+use crate::contacts::ContactCache;
+use crate::controller::Input;
+use crate::joints::{Joint, JointEvent, JointId};
+use crate::math::{add, closest_point_on_segment, closest_points_segments, cross, dot, length, negate, normalize, rotate, scale, sub, to_world};
+use crate::solver::{ContactSolver, Manifold};
+use crate::things::{capsule_segment, centre_sprite, chain_points, CollisionType, Dynamics, InvalidSprite, Shape, Sprite, Thing};
+
+// Physics always advances in steps of this size (seconds), whatever the frame rate
+pub const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
+// Cap on steps per update, so a long frame can't snowball into longer and longer updates
+const MAX_STEPS_PER_UPDATE: u32 = 5;
+const PHYSICS_SUBSTEPS: u32 = 2;
+
+pub const GRAVITY: f32 = 9.8 * 360.0; // px/s^2, scaled up to speed things along
+const TERMINAL_VELOCITY: f32 = 1800.0; // px/s, along the direction of gravity
+
+// Bodies slower than this for TIME_TO_SLEEP seconds go to sleep, together with
+// everything they touch
+const SLEEP_LINEAR_VELOCITY: f32 = 15.0; // px/s
+const SLEEP_ANGULAR_VELOCITY: f32 = 45.0; // degrees/s
+const TIME_TO_SLEEP: f32 = 1.0;
+// Faces this close (px) already count as touching, so a box resting flat keeps
+// both corners in contact from one substep to the next instead of rocking
+pub(crate) const CONTACT_SKIN: f32 = 0.5;
+
+// How close a moving kinematic shape has to come to wake a sleeping one
+const WAKE_MARGIN: f32 = 20.0;
+// How far gravity has to turn (degrees) or change in size (fraction) since it
+// last woke everything to wake them again, so sensor noise lets things sleep
+const GRAVITY_WAKE_ANGLE: f32 = 1.0;
+const GRAVITY_WAKE_RATIO: f32 = 0.01;
+
+// Bisection steps when narrowing down a CCD time of impact
+const TOI_ITERATIONS: u32 = 8;
 
-const TERMINAL_VELOCITY: f32 = 30.0;
-
-#[derive(Default)]
 pub struct CollisionSpace {
     pub(crate) shapes: HashMap<Id, Shape>,
+    gravity: (f32, f32), // px/s^2
+    woken_gravity: (f32, f32), // Gravity when it last woke everything
+    accumulator: f32, // Frame time not yet simulated (seconds)
+    broadphase: Box<dyn Broadphase>,
+    islands: HashMap<Id, u32>, // Island of each sleeping shape, they wake together
+    island_members: HashMap<u32, Vec<Id>>, // Sleeping shapes in each island
+    next_island: u32,
+    kinematic_poses: HashMap<Id, Pose>, // Where kinematic shapes were last step
+    sweep_poses: HashMap<Id, Pose>, // Where CCD and kinematic shapes were after the last resolve
+    solver: ContactSolver,
+    contacts: ContactCache,
+    joints: BTreeMap<JointId, Joint>, // Ordered, so they're solved in the same order every step
+    next_joint: JointId,
+    joint_events: Vec<JointEvent>, // Since the start of the last update
+}
+
+type Pose = (f32, f32, f32); // x, y, rotation (degrees)
+
+impl Default for CollisionSpace {
+    fn default() -> Self {
+        Self {
+            shapes: HashMap::new(),
+            gravity: (0.0, GRAVITY),
+            woken_gravity: (0.0, GRAVITY),
+            accumulator: 0.0,
+            broadphase: Box::new(UniformGrid::default()),
+            islands: HashMap::new(),
+            island_members: HashMap::new(),
+            next_island: 0,
+            kinematic_poses: HashMap::new(),
+            sweep_poses: HashMap::new(),
+            solver: ContactSolver::default(),
+            contacts: ContactCache::default(),
+            joints: BTreeMap::new(),
+            next_joint: 0,
+            joint_events: Vec::new(),
+        }
+    }
 }
 
 struct CollisionInfo {
-    normal: (f32, f32),    // Direction to push objects apart
+    normal: (f32, f32),    // Direction to push objects apart, from A towards B
     penetration: f32,       // How much they overlap
+    contacts: [(f32, f32); 2], // Where they touch (world space)
+    depths: [f32; 2],       // Overlap at each contact
+    features: [u32; 2],     // Which corner/edge/child made each contact, stable between steps
+    contact_count: usize,   // 1 for a point, 2 for an edge
+}
+
+impl CollisionInfo {
+    fn single(normal: (f32, f32), penetration: f32, contact: (f32, f32)) -> Self {
+        CollisionInfo {
+            normal,
+            penetration,
+            contacts: [contact, contact],
+            depths: [penetration, penetration],
+            features: [0, 0],
+            contact_count: 1,
+        }
+    }
+
+    fn contacts(&self) -> &[(f32, f32)] {
+        &self.contacts[..self.contact_count]
+    }
+
+    fn depths(&self) -> &[f32] {
+        &self.depths[..self.contact_count]
+    }
+
+    fn features(&self) -> &[u32] {
+        &self.features[..self.contact_count]
+    }
+
+    // Folds in which part of a shape (child, chain segment, ...) the contacts came
+    // from, so contacts from different parts keep different features
+    fn tagged(mut self, tag: u32) -> Self {
+        for feature in &mut self.features {
+            *feature = feature.wrapping_mul(31).wrapping_add(tag + 1);
+        }
+        self
+    }
+
+    // Same collision seen from the other shape
+    fn flipped(self) -> Self {
+        CollisionInfo {
+            normal: negate(self.normal),
+            ..self
+        }
+    }
 }
 
 impl CollisionSpace {
@@ -24,149 +139,553 @@ impl CollisionSpace {
                 restitution: f32,
                 dynamics: Dynamics
     ) {
-        let new_shape = Shape {
-            transform,
-            collision_type,
-            dynamics,
-            restitution,
-            colliding: false,
-        };
+        let new_shape = Shape::new(transform, collision_type, dynamics, restitution);
 
         self.shapes.insert(id, new_shape);
     }
 
-    pub(crate) fn update(&mut self) {
+    pub fn gravity(&self) -> (f32, f32) {
+        self.gravity
+    }
+
+    // Small changes don't wake sleeping shapes, but they add up: once gravity
+    // has drifted far enough from where it last woke them, they all wake
+    pub fn set_gravity(&mut self, gravity_x: f32, gravity_y: f32) {
+        self.gravity = (gravity_x, gravity_y);
+        if gravity_changed(self.woken_gravity, self.gravity) {
+            self.woken_gravity = self.gravity;
+            self.wake_all();
+        }
+    }
+
+    // Replaces how candidate pairs are found, a `UniformGrid` by default
+    pub fn set_broadphase(&mut self, broadphase: Box<dyn Broadphase>) {
+        self.broadphase = broadphase;
+    }
+
+    // Contacts from the last substep, with their impulses and age
+    pub fn contacts(&self) -> &ContactCache {
+        &self.contacts
+    }
+
+    pub fn solver_iterations(&self) -> u32 {
+        self.solver.iterations
+    }
+
+    // Passes the contact solver makes over all contacts each substep. More is
+    // stiffer stacks for more time.
+    pub fn set_solver_iterations(&mut self, iterations: u32) {
+        self.solver.iterations = iterations;
+    }
+
+    // Points gravity the way the accelerometer is tilted, keeping its magnitude.
+    // Tilting the Pico right (positive x) makes things fall to the right.
+    pub fn set_gravity_from_tilt(&mut self, input: &Input) {
+        // Board on its edge, the reading says nothing about the tilt
+        if (input.x * input.x + input.z * input.z).sqrt() < 0.1 {
+            return;
+        }
+
+        let magnitude = (self.gravity.0 * self.gravity.0 + self.gravity.1 * self.gravity.1).sqrt();
+        let angle = input.x.atan2(input.z);
+
+        self.set_gravity(angle.sin() * magnitude, angle.cos() * magnitude);
+    }
+
+    // Wakes a sleeping shape along with the rest of its island
+    pub fn wake(&mut self, id: Id) {
+        let island = self.islands.remove(&id);
+        if let Some(shape) = self.shapes.get_mut(&id) {
+            shape.wake();
+        }
+
+        let Some(island) = island else {
+            return;
+        };
+
+        for member in self.island_members.remove(&island).unwrap_or_default() {
+            self.islands.remove(&member);
+            if let Some(shape) = self.shapes.get_mut(&member) {
+                shape.wake();
+            }
+        }
+    }
+
+    pub fn wake_all(&mut self) {
+        self.islands.clear();
+        self.island_members.clear();
+        for shape in self.shapes.values_mut() {
+            shape.wake();
+        }
+    }
+
+    // Adds a joint between shapes already in the space, None if one isn't or
+    // if both ends are on the same body
+    pub fn add_joint(&mut self, joint: Joint) -> Option<JointId> {
+        if !joint.bodies().all(|id| self.shapes.contains_key(&id)) || joint.body_b == Some(joint.body_a) {
+            return None;
+        }
+
+        for id in joint.bodies() {
+            self.wake(id);
+        }
+
+        let id = self.next_joint;
+        self.next_joint += 1;
+        self.joints.insert(id, joint);
+        Some(id)
+    }
+
+    pub fn remove_joint(&mut self, id: JointId) -> Option<Joint> {
+        let joint = self.joints.remove(&id)?;
+        for id in joint.bodies() {
+            self.wake(id);
+        }
+
+        Some(joint)
+    }
+
+    pub fn joint(&self, id: JointId) -> Option<&Joint> {
+        self.joints.get(&id)
+    }
+
+    pub fn joint_mut(&mut self, id: JointId) -> Option<&mut Joint> {
+        self.joints.get_mut(&id)
+    }
+
+    pub fn joints(&self) -> impl Iterator<Item = (JointId, &Joint)> {
+        self.joints.iter().map(|(&id, joint)| (id, joint))
+    }
+
+    // Changing speed wakes the bodies, a sleeping lever wouldn't notice otherwise
+    pub fn set_motor_speed(&mut self, id: JointId, speed: f32) {
+        let Some(joint) = self.joints.get_mut(&id) else { return };
+        let Some(motor) = joint.motor_mut() else { return };
+        if motor.speed == speed {
+            return;
+        }
+
+        motor.speed = speed;
+        let bodies: Vec<Id> = joint.bodies().collect();
+        for id in bodies {
+            self.wake(id);
+        }
+    }
+
+    // Where the two ends of a joint are in world space
+    pub fn joint_anchors(&self, id: JointId) -> Option<((f32, f32), (f32, f32))> {
+        self.joints.get(&id).map(|joint| joint.anchors(&self.shapes))
+    }
+
+    // Joints that broke during the last update
+    pub fn joint_events(&self) -> &[JointEvent] {
+        &self.joint_events
+    }
+
+    // Advances the simulation by `dt` seconds of frame time, in fixed steps.
+    // Leftover time is carried over to the next update. A dt that isn't a
+    // positive number is ignored, so one bad frame time can't stall the world.
+    pub fn update(&mut self, dt: f32) {
+        self.joint_events.clear();
+        if !dt.is_finite() || dt <= 0.0 {
+            return;
+        }
+        self.accumulator += dt;
+
+        let mut steps = 0;
+        while self.accumulator >= FIXED_TIMESTEP {
+            if steps == MAX_STEPS_PER_UPDATE {
+                // Too far behind to catch up, drop the backlog
+                self.accumulator = 0.0;
+                break;
+            }
 
-        const PHYSICS_SUBSTEPS: u32 = 2;
+            self.step(FIXED_TIMESTEP);
+            self.accumulator -= FIXED_TIMESTEP;
+            steps += 1;
+        }
+    }
 
-        // 1. Apply gravity (continuous force) to dynamic objects
-        self.handle_gravity();
+    fn step(&mut self, dt: f32) {
+        let substep_dt = dt / PHYSICS_SUBSTEPS as f32;
+        self.drive_kinematics(dt);
+        let moving = self.moving_kinematics(dt);
+        self.contacts.begin_step();
+        // Joined bodies sleep and wake together, like ones in contact
+        let mut touching: Vec<(Id, Id)> = self.joints.values()
+            .filter_map(|joint| Some((joint.body_a, joint.body_b?)))
+            .collect();
+
+        // Settling is judged by how far bodies actually moved over the step
+        let start_poses: HashMap<Id, Pose> = self.shapes.iter()
+            .filter(|(_, shape)| !shape.sleeping)
+            .map(|(&id, shape)| (id, pose(&shape.transform)))
+            .collect();
 
         for _ in 0..PHYSICS_SUBSTEPS {
+            // Kinematic shapes move at the velocity drive_kinematics gave them,
+            // in integration below like everything else
+
+            // 1. Apply gravity (continuous force) to dynamic objects. Per substep,
+            // so each solve has the same weight to hold up and warm starts match.
+            self.handle_gravity(substep_dt);
 
-            // No need to update kinematic objects here
+            // 2. Stop CCD bodies where they first hit something they'd have passed through
+            self.sweep_ccd();
 
-            // 2. Detect collisions
+            // 3. Detect collisions
             let collisions = self.detect_collisions();
             self.reset_colliding_debug();
             self.set_colliding_debug(&collisions);
+            touching.extend(collisions.iter().map(|&(id_a, id_b, _)| (id_a, id_b)));
 
-            // 3. Resolve collisions (push apart + bounce/slide)
-            self.resolve_collisions(collisions);
+            // 4. Resolve collisions (bounce/slide, working off any overlap)
+            self.resolve_collisions(collisions, substep_dt);
+            self.break_joints(substep_dt);
+            self.record_sweep_poses();
 
-            // 4. Integrate motion (velocity -> position)
-            self.integrate_motion_substep(1.0 / PHYSICS_SUBSTEPS as f32);
+            // 5. Integrate motion (velocity -> position)
+            self.integrate_motion_substep(substep_dt);
         }
+
+        // 6. Put islands that have settled to sleep
+        self.update_sleep(dt, &start_poses, &touching, &moving);
+        self.arrive_kinematics();
     }
 
-    fn integrate_motion_substep(&mut self, dt_fraction: f32) {
-        for thing in self.shapes.values_mut() {
-            thing.transform.x_pos += thing.transform.vel_x * dt_fraction;
-            thing.transform.y_pos += thing.transform.vel_y * dt_fraction;
+    // Kinematic shapes with a target pose get the velocity that takes them
+    // there over this step, so their surface moves with them.
+    fn drive_kinematics(&mut self, dt: f32) {
+        for shape in self.shapes.values_mut().filter(|shape| matches!(shape.dynamics, Dynamics::Kinematic)) {
+            let Some((x_pos, y_pos, rotation)) = shape.target_pose else { continue };
+            let transform = &mut shape.transform;
+            transform.vel_x = (x_pos - transform.x_pos) / dt;
+            transform.vel_y = (y_pos - transform.y_pos) / dt;
+            // The short way round, so 350 degrees to 10 is a 20 degree turn
+            let turn = (rotation - transform.rotation).rem_euclid(360.0);
+            let turn = if turn > 180.0 { turn - 360.0 } else { turn };
+            transform.angular_vel = turn / dt;
         }
     }
 
-    fn handle_gravity(&mut self)  {
+    // Puts kinematic shapes that had a target exactly on it and stops them there
+    fn arrive_kinematics(&mut self) {
+        for shape in self.shapes.values_mut().filter(|shape| matches!(shape.dynamics, Dynamics::Kinematic)) {
+            let Some((x_pos, y_pos, rotation)) = shape.target_pose.take() else { continue };
+            let transform = &mut shape.transform;
+            transform.x_pos = x_pos;
+            transform.y_pos = y_pos;
+            transform.rotation = rotation;
+            transform.vel_x = 0.0;
+            transform.vel_y = 0.0;
+            transform.angular_vel = 0.0;
+        }
+    }
 
-        for thing in self.shapes.values_mut()
-            .filter(|shape| matches!(shape.dynamics, Dynamics::Dynamic))
-        {
+    // Kinematic shapes that have moved since the last step, or are about to.
+    // Sleeping shapes near where they were, are or will be by the end of the
+    // step get woken up, the broadphase alone would miss a platform that moved
+    // away from them.
+    fn moving_kinematics(&mut self, dt: f32) -> HashSet<Id> {
+        let mut moving = HashSet::new();
+        let mut swept = Vec::new();
+
+        for (&id, shape) in &self.shapes {
+            if !matches!(shape.dynamics, Dynamics::Kinematic) {
+                continue;
+            }
 
-            if thing.transform.vel_y < TERMINAL_VELOCITY {
-                thing.transform.accel_y = 9.8 / 60.0 * 6.0; // Per frame, scaled by some factor to speed up
-                thing.transform.vel_y += thing.transform.accel_y
+            let transform = &shape.transform;
+            let current = pose(transform);
+            let last = self.kinematic_poses.insert(id, current);
+            let moved = last.is_some_and(|last| last != current);
+
+            if moved || transform.vel_x != 0.0 || transform.vel_y != 0.0 || transform.angular_vel != 0.0 {
+                moving.insert(id);
+
+                let next = (
+                    transform.x_pos + transform.vel_x * dt,
+                    transform.y_pos + transform.vel_y * dt,
+                    transform.rotation + transform.angular_vel * dt,
+                );
+
+                let (mut min_x, mut min_y, mut max_x, mut max_y) = shape.aabb();
+                for (x_pos, y_pos, rotation) in last.into_iter().chain([next]) {
+                    let other_transform = things::Transform::new(x_pos, y_pos, transform.width, transform.height, rotation);
+                    let (other_min_x, other_min_y, other_max_x, other_max_y) = things::collision_aabb(&shape.collision_type, &other_transform);
+                    min_x = min_x.min(other_min_x);
+                    min_y = min_y.min(other_min_y);
+                    max_x = max_x.max(other_max_x);
+                    max_y = max_y.max(other_max_y);
+                }
+                swept.push((min_x - WAKE_MARGIN, min_y - WAKE_MARGIN, max_x + WAKE_MARGIN, max_y + WAKE_MARGIN));
             }
         }
-    }
 
-    fn detect_collisions(&mut self) -> Vec<(Id, Id, CollisionInfo)> {
-        let mut collisions = Vec::new();
-        let ids: Vec<_> = self.shapes.keys().copied().collect();
+        let mut to_wake: Vec<Id> = self.shapes.iter()
+            .filter(|(_, shape)| shape.sleeping)
+            .filter(|(_, shape)| {
+                let (min_x, min_y, max_x, max_y) = shape.aabb();
+                swept.iter().any(|&(s_min_x, s_min_y, s_max_x, s_max_y)| {
+                    min_x <= s_max_x && s_min_x <= max_x && min_y <= s_max_y && s_min_y <= max_y
+                })
+            })
+            .map(|(&id, _)| id)
+            .collect();
+
+        // However far away they hang from it
+        for joint in self.joints.values() {
+            if joint.bodies().any(|id| moving.contains(&id)) {
+                to_wake.extend(joint.bodies());
+            }
+        }
 
-        for i in 0..ids.len() {
-            for j in i+1..ids.len() {
-                let shape_a = &self.shapes[&ids[i]];
-                let shape_b = &self.shapes[&ids[j]];
+        for id in to_wake {
+            self.wake(id);
+        }
 
-                // Skip if neither is dynamic (static-static don't need collision)
-                let needs_check = matches!(shape_a.dynamics, Dynamics::Dynamic)
-                    || matches!(shape_b.dynamics, Dynamics::Dynamic);
+        moving
+    }
 
-                if !needs_check {
+    // Moves CCD bodies back along their path to where they first touched a
+    // kinematic shape, if they got past it since the last resolve. The path is
+    // taken in the kinematic shape's frame, so a platform swinging through a
+    // ball is caught as well as a ball flying through a platform.
+    fn sweep_ccd(&mut self) {
+        let ids: Vec<Id> = self.shapes.iter()
+            .filter(|(_, shape)| shape.ccd && matches!(shape.dynamics, Dynamics::Dynamic) && !shape.sleeping)
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in ids {
+            let shape = &self.shapes[&id];
+            let Some(&from) = self.sweep_poses.get(&id) else { continue };
+            let to = pose(&shape.transform);
+
+            let mut earliest: Option<(f32, Pose)> = None;
+            for (other_id, other) in &self.shapes {
+                if !matches!(other.dynamics, Dynamics::Kinematic) {
                     continue;
                 }
 
-                // Quick AABB check first
-                let margin = 20.0; // Add some margin for safety
-                if (shape_a.transform.x_pos - shape_b.transform.x_pos).abs() >
-                    (shape_a.transform.width as f32 + shape_b.transform.width as f32) / 2.0 + margin {
-                    continue;
-                }
-                if (shape_a.transform.y_pos - shape_b.transform.y_pos).abs() >
-                    (shape_a.transform.height as f32 + shape_b.transform.height as f32) / 2.0 + margin {
-                    continue;
+                let other_to = pose(&other.transform);
+                let other_from = self.sweep_poses.get(other_id).copied().unwrap_or(other_to);
+                let start = to_local(from, other_from);
+                let end = to_local(to, other_to);
+
+                if let Some((toi, hit)) = time_of_impact(shape, start, end, other)
+                    && earliest.is_none_or(|(earliest_toi, _)| toi < earliest_toi)
+                {
+                    earliest = Some((toi, from_local(hit, other_to)));
                 }
+            }
 
-                let collision = match (&shape_a.collision_type, &shape_b.collision_type) {
-                    (CollisionType::Circle, CollisionType::Circle) => {
-                        let radius_a = shape_a.transform.width as f32 / 2.0;
-                        let radius_b = shape_b.transform.width as f32 / 2.0;
-                        detect_circle_circle(
-                            (shape_a.transform.x_pos, shape_a.transform.y_pos),
-                            radius_a,
-                            (shape_b.transform.x_pos, shape_b.transform.y_pos),
-                            radius_b,
-                        )
-                    }
-                    (CollisionType::Circle, CollisionType::Rectangle) => {
-                        let radius = shape_a.transform.width as f32 / 2.0;
-                        detect_circle_rect(
-                            (shape_a.transform.x_pos, shape_a.transform.y_pos),
-                            radius,
-                            (shape_b.transform.x_pos, shape_b.transform.y_pos),
-                            shape_b.transform.width as f32,
-                            shape_b.transform.height as f32,
-                            shape_b.transform.rotation,
-                        )
-                    }
-                    (CollisionType::Rectangle, CollisionType::Circle) => {
-                        let radius = shape_b.transform.width as f32 / 2.0;
-                        detect_circle_rect(
-                            (shape_b.transform.x_pos, shape_b.transform.y_pos),
-                            radius,
-                            (shape_a.transform.x_pos, shape_a.transform.y_pos),
-                            shape_a.transform.width as f32,
-                            shape_a.transform.height as f32,
-                            shape_a.transform.rotation,
-                        )
-                        //     .map(|mut info| {
-                        //     // Flip the normal since we swapped order
-                        //     info.normal = (-info.normal.0, -info.normal.1);
-                        //     info
-                        // })
+            if let Some((_, (x_pos, y_pos, rotation))) = earliest {
+                let transform = &mut self.shapes.get_mut(&id).unwrap().transform;
+                transform.x_pos = x_pos;
+                transform.y_pos = y_pos;
+                transform.rotation = rotation;
+            }
+        }
+    }
+
+    fn record_sweep_poses(&mut self) {
+        self.sweep_poses.clear();
+        self.sweep_poses.extend(self.shapes.iter()
+            .filter(|(_, shape)| shape.ccd || matches!(shape.dynamics, Dynamics::Kinematic))
+            .map(|(&id, shape)| (id, pose(&shape.transform))));
+    }
+
+    // Islands are groups of dynamic shapes connected through contacts. An island
+    // sleeps once all of its shapes have been slow for long enough.
+    fn update_sleep(&mut self,
+                    dt: f32,
+                    start_poses: &HashMap<Id, Pose>,
+                    touching: &[(Id, Id)],
+                    moving: &HashSet<Id>,
+    ) {
+        let awake = |shape: &Shape| matches!(shape.dynamics, Dynamics::Dynamic) && !shape.sleeping;
+
+        for (id, shape) in self.shapes.iter_mut().filter(|(_, shape)| awake(shape)) {
+            let Some(&(x, y, rotation)) = start_poses.get(id) else { continue };
+            let transform = &shape.transform;
+            let speed = length((transform.x_pos - x, transform.y_pos - y)) / dt;
+            let angular_speed = (transform.rotation - rotation).abs() / dt;
+            if speed < SLEEP_LINEAR_VELOCITY && angular_speed < SLEEP_ANGULAR_VELOCITY {
+                shape.sleep_time += dt;
+            } else {
+                shape.sleep_time = 0.0;
+            }
+        }
+
+        // Union-find over the contacts between awake dynamic shapes
+        let mut parent: HashMap<Id, Id> = self.shapes.iter()
+            .filter(|(_, shape)| awake(shape))
+            .map(|(&id, _)| (id, id))
+            .collect();
+
+        fn find(parent: &mut HashMap<Id, Id>, id: Id) -> Id {
+            let mut root = id;
+            while parent[&root] != root {
+                root = parent[&root];
+            }
+            parent.insert(id, root);
+            root
+        }
+
+        for &(id_a, id_b) in touching {
+            // Riding a moving platform isn't resting
+            if moving.contains(&id_a) || moving.contains(&id_b) {
+                for id in [id_a, id_b] {
+                    if let Some(shape) = self.shapes.get_mut(&id) {
+                        shape.sleep_time = 0.0;
                     }
-                    _ => None,
-                };
+                }
+            }
+
+            if parent.contains_key(&id_a) && parent.contains_key(&id_b) {
+                let root_a = find(&mut parent, id_a);
+                let root_b = find(&mut parent, id_b);
+                parent.insert(root_a, root_b);
+            }
+        }
+
+        let mut islands: HashMap<Id, Vec<Id>> = HashMap::new();
+        let ids: Vec<_> = parent.keys().copied().collect();
+        for id in ids {
+            let root = find(&mut parent, id);
+            islands.entry(root).or_default().push(id);
+        }
+
+        for members in islands.into_values() {
+            if members.iter().any(|id| self.shapes[id].sleep_time < TIME_TO_SLEEP) {
+                continue;
+            }
+
+            for &id in &members {
+                self.shapes.get_mut(&id).unwrap().fall_asleep();
+                self.islands.insert(id, self.next_island);
+            }
+            self.island_members.insert(self.next_island, members);
+            self.next_island = self.next_island.wrapping_add(1);
+        }
+    }
 
-                if let Some(info) = collision {
+    fn integrate_motion_substep(&mut self, dt: f32) {
+        for thing in self.shapes.values_mut().filter(|shape| !shape.sleeping) {
+            if matches!(thing.dynamics, Dynamics::Dynamic) {
+                thing.transform.angular_vel += thing.transform.angular_accel * dt;
+            }
+
+            thing.transform.x_pos += thing.transform.vel_x * dt;
+            thing.transform.y_pos += thing.transform.vel_y * dt;
+            thing.transform.rotation += thing.transform.angular_vel * dt;
+        }
+    }
 
-                    println!("COLLISION: {} vs {}", ids[i], ids[j]);
-                    println!("  Normal: ({:.3}, {:.3})", info.normal.0, info.normal.1);
-                    println!("  Penetration: {:.3}", info.penetration);
-                    println!("  Shape A pos: ({:.1}, {:.1})", shape_a.transform.x_pos, shape_a.transform.y_pos);
-                    println!("  Shape B pos: ({:.1}, {:.1})", shape_b.transform.x_pos, shape_b.transform.y_pos);
+    fn handle_gravity(&mut self, dt: f32)  {
+        let (gravity_x, gravity_y) = self.gravity;
+        let magnitude = (gravity_x * gravity_x + gravity_y * gravity_y).sqrt();
+        if magnitude == 0.0 {
+            return;
+        }
 
-                    collisions.push((ids[i], ids[j], info));
+        for thing in self.shapes.values_mut()
+            .filter(|shape| matches!(shape.dynamics, Dynamics::Dynamic) && !shape.sleeping)
+        {
+            // Only the speed along gravity is capped, sideways motion is left alone
+            let falling_speed = (thing.transform.vel_x * gravity_x
+                + thing.transform.vel_y * gravity_y) / magnitude;
+
+            if falling_speed < TERMINAL_VELOCITY {
+                thing.transform.accel_x = gravity_x;
+                thing.transform.accel_y = gravity_y;
+                thing.transform.vel_x += thing.transform.accel_x * dt;
+                thing.transform.vel_y += thing.transform.accel_y * dt;
+            }
+        }
+    }
+
+    fn detect_collisions(&mut self) -> Vec<(Id, Id, CollisionInfo)> {
+        let mut collisions = Vec::new();
+        let mut to_wake = Vec::new();
+
+        // Sorted so collisions resolve in the same order every step, whichever broadphase
+        let mut pairs = self.broadphase.pairs(&self.shapes);
+        pairs.sort_unstable();
+        pairs.dedup();
+
+        // Jointed bodies pass through each other unless the joint says otherwise
+        let joined: HashSet<(Id, Id)> = self.joints.values()
+            .filter(|joint| !joint.collide_connected)
+            .filter_map(|joint| {
+                let id_b = joint.body_b?;
+                Some((joint.body_a.min(id_b), joint.body_a.max(id_b)))
+            })
+            .collect();
+        pairs.retain(|&(id_a, id_b)| !joined.contains(&(id_a.min(id_b), id_a.max(id_b))));
+
+        for (id_a, id_b) in pairs {
+            let shape_a = &self.shapes[&id_a];
+            let shape_b = &self.shapes[&id_b];
+
+            // Skip if neither is dynamic (static-static don't need collision)
+            let needs_check = matches!(shape_a.dynamics, Dynamics::Dynamic)
+                || matches!(shape_b.dynamics, Dynamics::Dynamic);
+
+            if !needs_check {
+                continue;
+            }
+
+            // Sleeping shapes only get a narrowphase check against awake ones
+            let awake_a = matches!(shape_a.dynamics, Dynamics::Dynamic) && !shape_a.sleeping;
+            let awake_b = matches!(shape_b.dynamics, Dynamics::Dynamic) && !shape_b.sleeping;
+            if !awake_a && !awake_b {
+                continue;
+            }
+
+            let collisions_ab = detect_shapes(
+                &shape_a.collision_type,
+                &shape_a.transform,
+                &shape_b.collision_type,
+                &shape_b.transform,
+            );
+
+            for info in collisions_ab {
+
+                // Only built when debugging, formatting every contact adds up
+                if DEBUG {
+                    print_debug(&format!("COLLISION: {} vs {}", id_a, id_b));
+                    print_debug(&format!("  Normal: ({:.3}, {:.3})", info.normal.0, info.normal.1));
+                    print_debug(&format!("  Penetration: {:.3}", info.penetration));
+                    print_debug(&format!("  Shape A pos: ({:.1}, {:.1})", shape_a.transform.x_pos, shape_a.transform.y_pos));
+                    print_debug(&format!("  Shape B pos: ({:.1}, {:.1})", shape_b.transform.x_pos, shape_b.transform.y_pos));
+                }
+
+                collisions.push((id_a, id_b, info));
+            }
+
+            // Touched by an awake shape
+            if collisions.last().is_some_and(|&(a, b, _)| (a, b) == (id_a, id_b)) {
+                if shape_a.sleeping {
+                    to_wake.push(id_a);
+                }
+                if shape_b.sleeping {
+                    to_wake.push(id_b);
                 }
             }
         }
 
+        for id in to_wake {
+            self.wake(id);
+        }
+
         collisions
     }
 
     fn reset_colliding_debug(&mut self) {
         // reset set_colliding for all shapes
-        for (id, shape) in &mut self.shapes {
+        for shape in self.shapes.values_mut() {
             shape.colliding = false
         }
     }
@@ -182,126 +701,417 @@ impl CollisionSpace {
         }
     }
 
-    fn resolve_collisions(&mut self, collisions: Vec<(Id, Id, CollisionInfo)>) {
-        for (id_a, id_b, info) in collisions {
-            // We need to handle different dynamics combinations
-            let dynamics_a = self.shapes[&id_a].dynamics;
-            let dynamics_b = self.shapes[&id_b].dynamics;
+    // Solves the contacts together with the joints holding awake bodies
+    fn resolve_collisions(&mut self, collisions: Vec<(Id, Id, CollisionInfo)>, dt: f32) {
+        let manifolds = collisions.iter()
+            .map(|(id_a, id_b, info)| Manifold::new(*id_a, *id_b, info.normal, info.contacts(), info.depths(), info.features(), &self.shapes))
+            .collect();
 
-            match (dynamics_a, dynamics_b) {
-                // Both dynamic - push both apart
-                (Dynamics::Dynamic, Dynamics::Dynamic) => {
-                    self.resolve_dynamic_dynamic(id_a, id_b, &info);
-                }
-                // One dynamic, one kinematic/static - only push the dynamic one
-                (Dynamics::Dynamic, _) => {
-                    self.resolve_dynamic_static(id_a, &info, false);
-                }
-                (_, Dynamics::Dynamic) => {
-                    // Flip the normal since b is dynamic
-                    // let flipped_info = CollisionInfo {
-                    //     normal: (-info.normal.0, -info.normal.1),
-                    //     penetration: info.penetration,
-                    // };
-                    self.resolve_dynamic_static(id_b, &info, false);
-                }
-                _ => {} // Both static/kinematic - no resolution needed
+        let shapes = &self.shapes;
+        let awake = |id: Id| shapes.get(&id)
+            .is_some_and(|shape| matches!(shape.dynamics, Dynamics::Dynamic) && !shape.sleeping);
+        let mut joints: Vec<&mut Joint> = self.joints.values_mut()
+            .filter(|joint| joint.bodies().any(awake))
+            .collect();
+
+        self.solver.solve(&mut self.shapes, &mut self.contacts, manifolds, &mut joints, dt);
+    }
+
+    // Joints pulled past their break force or torque come off. The bodies are
+    // already moving from that solve; from the next substep they're free.
+    fn break_joints(&mut self, dt: f32) {
+        let broken: Vec<JointId> = self.joints.iter()
+            .filter(|(_, joint)| joint.breaks(dt))
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in broken {
+            if let Some(joint) = self.remove_joint(id) {
+                self.joint_events.push(JointEvent::Broken { id, joint });
             }
         }
     }
+}
+
+// Narrowphase for one pair of bodies, testing each child of compound shapes.
+// The normals point from A to B.
+fn detect_shapes(
+    type_a: &CollisionType,
+    transform_a: &things::Transform,
+    type_b: &CollisionType,
+    transform_b: &things::Transform,
+) -> Vec<CollisionInfo> {
+    if let CollisionType::Compound(children) = type_a {
+        let collisions = children.iter().enumerate()
+            .flat_map(|(i, child)| detect_shapes(&child.collision_type, &child.world_transform(transform_a), type_b, transform_b)
+                .into_iter()
+                .map(move |info| info.tagged(i as u32)))
+            .collect();
+        return merge_collisions(collisions);
+    }
+
+    if let CollisionType::Compound(children) = type_b {
+        let collisions = children.iter().enumerate()
+            .flat_map(|(i, child)| detect_shapes(type_a, transform_a, &child.collision_type, &child.world_transform(transform_b))
+                .into_iter()
+                .map(move |info| info.tagged(i as u32)))
+            .collect();
+        return merge_collisions(collisions);
+    }
+
+    if matches!(type_a, CollisionType::Edge | CollisionType::Chain(_)) {
+        return detect_chain(&chain_points(type_a, transform_a), type_b, transform_b);
+    }
 
-    fn resolve_dynamic_dynamic(&mut self, id_a: Id, id_b: Id, info: &CollisionInfo) {
-        // Get both shapes (we know they exist)
-        let correction = info.penetration / 2.0;
+    if matches!(type_b, CollisionType::Edge | CollisionType::Chain(_)) {
+        return detect_chain(&chain_points(type_b, transform_b), type_a, transform_a)
+            .into_iter()
+            .map(CollisionInfo::flipped)
+            .collect();
+    }
+
+    detect_pair(type_a, transform_a, type_b, transform_b).into_iter().collect()
+}
 
-        // Push A away from B
-        if let Some(shape_a) = self.shapes.get_mut(&id_a) {
-            shape_a.transform.x_pos -= info.normal.0 * correction;
-            shape_a.transform.y_pos -= info.normal.1 * correction;
+// Every segment of an edge or chain (world space points) against a shape.
+// The normals point from the chain to the shape.
+fn detect_chain(points: &[(f32, f32)], collision_type: &CollisionType, transform: &things::Transform) -> Vec<CollisionInfo> {
+    if points.len() < 2 {
+        return Vec::new();
+    }
+
+    // A closed chain ends where it starts, so its first and last segments are neighbours
+    let closed = points.len() > 2 && length(sub(points[0], points[points.len() - 1])) < 0.01;
+
+    let mut collisions = Vec::new();
+    for i in 0..points.len() - 1 {
+        let prev = match i {
+            0 if closed => Some(points[points.len() - 2]),
+            0 => None,
+            _ => Some(points[i - 1]),
+        };
+        let next = match points.get(i + 2) {
+            Some(&next) => Some(next),
+            None if closed => Some(points[1]),
+            None => None,
+        };
+
+        if let Some(info) = detect_segment(points[i], points[i + 1], prev, next, collision_type, transform) {
+            collisions.push(info.tagged(i as u32));
         }
+    }
+
+    merge_collisions(collisions)
+}
 
-        // Push B away from A
-        if let Some(shape_b) = self.shapes.get_mut(&id_b) {
-            shape_b.transform.x_pos += info.normal.0 * correction;
-            shape_b.transform.y_pos += info.normal.1 * correction;
+// One segment (v1 to v2) of a chain against a shape, `prev` and `next` are the
+// neighbouring chain points if there are any. The normal points from the segment to the shape.
+fn detect_segment(
+    v1: (f32, f32),
+    v2: (f32, f32),
+    prev: Option<(f32, f32)>,
+    next: Option<(f32, f32)>,
+    collision_type: &CollisionType,
+    transform: &things::Transform,
+) -> Option<CollisionInfo> {
+    let info = match collision_type {
+        CollisionType::Circle => detect_circle_capsule(
+            (transform.x_pos, transform.y_pos),
+            transform.width as f32 / 2.0,
+            v1,
+            v2,
+            0.0,
+        ),
+        CollisionType::Rectangle | CollisionType::Polygon(_) => {
+            detect_polygon_polygon(&[v1, v2], &world_vertices(collision_type, transform))
+        }
+        CollisionType::Capsule => {
+            let (start, end, radius) = capsule_segment(transform);
+            detect_capsule_polygon(start, end, radius, &[v1, v2])
         }
+        _ => None,
+    }?;
+
+    // Segments are two-sided, face the side the shape is on
+    let edge = sub(v2, v1);
+    let mut face_normal = edge_normal(v1, v2);
+    if dot(info.normal, face_normal) < 0.0 {
+        face_normal = negate(face_normal);
+    }
 
-        // Now handle velocity response (bounce)
-        self.apply_bounce(id_a, id_b, info);
+    if dot(info.normal, face_normal) > 0.999 {
+        return Some(info);
     }
 
-    fn resolve_dynamic_static(&mut self, dynamic_id: Id, info: &CollisionInfo, _is_kinematic: bool) {
-        if let Some(shape) = self.shapes.get_mut(&dynamic_id) {
-            // Push ALONG the normal (away from platform)
-            shape.transform.x_pos += info.normal.0 * info.penetration;  // Use +=
-            shape.transform.y_pos += info.normal.1 * info.penetration;  // Use +=
+    // Touching an end of the segment. Where the chain carries on, that's only a real
+    // corner if the shape is also past the neighbouring segment's face. Otherwise the
+    // normal would point sideways and catch shapes sliding over the joint.
+    let corner = if dot(info.normal, edge) < 0.0 {
+        prev.is_none_or(|prev| dot(info.normal, sub(v1, prev)) > 0.0)
+    } else {
+        next.is_none_or(|next| dot(info.normal, sub(next, v2)) < 0.0)
+    };
+
+    if corner {
+        Some(info)
+    } else {
+        detect_segment_face(v1, v2, face_normal, collision_type, transform)
+    }
+}
 
-            self.apply_bounce_static(dynamic_id, info);
+// Pushes a shape straight out of a segment's face, using only the parts of the
+// shape above the segment (the neighbouring segments handle the rest)
+fn detect_segment_face(
+    v1: (f32, f32),
+    v2: (f32, f32),
+    face_normal: (f32, f32),
+    collision_type: &CollisionType,
+    transform: &things::Transform,
+) -> Option<CollisionInfo> {
+    let (points, radius) = match collision_type {
+        CollisionType::Circle => (vec![(transform.x_pos, transform.y_pos)], transform.width as f32 / 2.0),
+        CollisionType::Capsule => {
+            let (start, end, radius) = capsule_segment(transform);
+            (vec![start, end], radius)
         }
+        _ => (world_vertices(collision_type, transform), 0.0),
+    };
+
+    let edge = sub(v2, v1);
+    let edge_length_sq = dot(edge, edge);
+    if edge_length_sq == 0.0 {
+        return None;
     }
 
-    fn apply_bounce(&mut self, id_a: Id, id_b: Id, info: &CollisionInfo) {
-        // Get velocities (need to borrow separately)
-        let (vel_a, vel_b, restitution_a, restitution_b) = {
-            let shape_a = &self.shapes[&id_a];
-            let shape_b = &self.shapes[&id_b];
-            (
-                (shape_a.transform.vel_x, shape_a.transform.vel_y),
-                (shape_b.transform.vel_x, shape_b.transform.vel_y),
-                shape_a.restitution,
-                shape_b.restitution,
-            )
+    let contacts = points.into_iter()
+        .enumerate()
+        .filter_map(|(i, point)| {
+            let along = dot(sub(point, v1), edge) / edge_length_sq;
+            let distance = dot(sub(point, v1), face_normal);
+            if distance < radius && (0.0..=1.0).contains(&along) {
+                let contact = sub(point, scale(face_normal, distance));
+                Some(CollisionInfo::single(face_normal, radius - distance, contact).tagged(i as u32))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    merge_collisions(contacts).pop()
+}
+
+// Children resting on the same surface each report a contact along the same normal.
+// Pushing the body out once per child would overshoot, so those are combined into
+// one collision with the deepest penetration and the two outermost contact points.
+fn merge_collisions(collisions: Vec<CollisionInfo>) -> Vec<CollisionInfo> {
+    let mut merged: Vec<CollisionInfo> = Vec::new();
+
+    for info in collisions {
+        let Some(existing) = merged.iter_mut().find(|other| dot(other.normal, info.normal) > 0.99) else {
+            merged.push(info);
+            continue;
         };
 
-        // Combined restitution (how bouncy the collision is)
-        let restitution = restitution_a * restitution_b;
+        let tangent = (-existing.normal.1, existing.normal.0);
+        let points_of = |info: &CollisionInfo| -> Vec<((f32, f32), f32, u32)> {
+            (0..info.contact_count).map(|i| (info.contacts[i], info.depths[i], info.features[i])).collect()
+        };
+        let mut points = points_of(existing);
+        points.extend(points_of(&info));
+
+        let along = |point: &((f32, f32), f32, u32)| dot(point.0, tangent);
+        let min = points.iter().copied().min_by(|a, b| along(a).total_cmp(&along(b))).unwrap();
+        let max = points.iter().copied().max_by(|a, b| along(a).total_cmp(&along(b))).unwrap();
+
+        existing.penetration = existing.penetration.max(info.penetration);
+        existing.contacts = [min.0, max.0];
+        existing.depths = [min.1, max.1];
+        existing.features = [min.2, max.2];
+        existing.contact_count = if length(sub(max.0, min.0)) > 0.01 { 2 } else { 1 };
+    }
 
-        // Relative velocity along collision normal
-        let rel_vel_x = vel_b.0 - vel_a.0;
-        let rel_vel_y = vel_b.1 - vel_a.1;
-        let vel_along_normal = rel_vel_x * info.normal.0 + rel_vel_y * info.normal.1;
+    merged
+}
 
-        // Don't resolve if velocities are separating
-        if vel_along_normal > 0.0 {
-            return;
-        }
+fn pose(transform: &things::Transform) -> Pose {
+    (transform.x_pos, transform.y_pos, transform.rotation)
+}
 
-        // Calculate impulse scalar
-        let impulse_magnitude = -(1.0 + restitution) * vel_along_normal / 2.0;
+// Pose relative to another pose (the frame), and back
+fn to_local(pose: Pose, frame: Pose) -> Pose {
+    let (x, y) = rotate(sub((pose.0, pose.1), (frame.0, frame.1)), -frame.2);
+    (x, y, pose.2 - frame.2)
+}
 
-        // Apply impulse to both objects
-        let impulse_x = info.normal.0 * impulse_magnitude;
-        let impulse_y = info.normal.1 * impulse_magnitude;
+fn from_local(pose: Pose, frame: Pose) -> Pose {
+    let (x, y) = to_world((pose.0, pose.1), (frame.0, frame.1), frame.2);
+    (x, y, pose.2 + frame.2)
+}
 
-        if let Some(shape_a) = self.shapes.get_mut(&id_a) {
-            shape_a.transform.vel_x -= impulse_x;
-            shape_a.transform.vel_y -= impulse_y;
-        }
+// Earliest time (0 to 1) and pose at which `shape`, moving from `start` to `end`,
+// touches `other` sitting at the origin. Samples the path in steps of a quarter of
+// the shape's size, then narrows the first hit down by bisection. Paths shorter
+// than a step are left to the normal collision checks. A shape already touching
+// at the start (resting on a tilting platform, say) is only stopped once it
+// would sink in by more than half a step further.
+fn time_of_impact(shape: &Shape, start: Pose, end: Pose, other: &Shape) -> Option<(f32, Pose)> {
+    let width = shape.transform.width;
+    let height = shape.transform.height;
+    let step = (width.min(height) as f32 / 4.0).max(1.0);
+    let distance = length(sub((end.0, end.1), (start.0, start.1)));
+    if distance < step {
+        return None;
+    }
 
-        if let Some(shape_b) = self.shapes.get_mut(&id_b) {
-            shape_b.transform.vel_x += impulse_x;
-            shape_b.transform.vel_y += impulse_y;
-        }
+    let other_transform = things::Transform::new(0.0, 0.0, other.transform.width, other.transform.height, 0.0);
+    let at = |t: f32| -> Pose {
+        (start.0 + (end.0 - start.0) * t, start.1 + (end.1 - start.1) * t, start.2 + (end.2 - start.2) * t)
+    };
+    let depth = |pose: Pose| {
+        let transform = things::Transform::new(pose.0, pose.1, width, height, pose.2);
+        detect_shapes(&shape.collision_type, &transform, &other.collision_type, &other_transform)
+            .iter()
+            .map(|info| info.penetration)
+            .max_by(f32::total_cmp)
+    };
+
+    // Nothing to hit if the swept box misses the other shape
+    let start_box = things::collision_aabb(&shape.collision_type, &things::Transform::new(start.0, start.1, width, height, start.2));
+    let end_box = things::collision_aabb(&shape.collision_type, &things::Transform::new(end.0, end.1, width, height, end.2));
+    let (min_x, min_y, max_x, max_y) = things::collision_aabb(&other.collision_type, &other_transform);
+    if start_box.0.min(end_box.0) > max_x || start_box.2.max(end_box.2) < min_x
+        || start_box.1.min(end_box.1) > max_y || start_box.3.max(end_box.3) < min_y {
+        return None;
     }
 
-    fn apply_bounce_static(&mut self, dynamic_id: Id, info: &CollisionInfo) {
-        if let Some(shape) = self.shapes.get_mut(&dynamic_id) {
-            let vel_along_normal = shape.transform.vel_x * info.normal.0
-                + shape.transform.vel_y * info.normal.1;
+    let allowed = depth(start).map_or(0.0, |start_depth| start_depth + step / 2.0);
+    let touches = |pose: Pose| depth(pose).is_some_and(|depth| depth > allowed);
 
-            let restitution = shape.restitution * 0.8;
-            let impulse = -(1.0 + restitution) * vel_along_normal;
+    let samples = (distance / step).ceil() as u32;
+    let mut clear = 0.0;
+    for i in 1..=samples {
+        let t = i as f32 / samples as f32;
+        if !touches(at(t)) {
+            clear = t;
+            continue;
+        }
 
-            shape.transform.vel_x += info.normal.0 * impulse;
-            shape.transform.vel_y += info.normal.1 * impulse;
+        // Ends just touching, so the contact gets resolved right away
+        let mut hit = t;
+        for _ in 0..TOI_ITERATIONS {
+            let mid = (clear + hit) / 2.0;
+            if touches(at(mid)) {
+                hit = mid;
+            } else {
+                clear = mid;
+            }
         }
+        return Some((hit, at(hit)));
     }
 
-    fn integrate_motion(&mut self) {
-        for thing in self.shapes.values_mut() {
-            thing.transform.x_pos += thing.transform.vel_x;
-            thing.transform.y_pos += thing.transform.vel_y;
+    None
+}
+
+// Narrowphase for one pair of shapes. The normal points from A to B.
+fn detect_pair(
+    type_a: &CollisionType,
+    transform_a: &things::Transform,
+    type_b: &CollisionType,
+    transform_b: &things::Transform,
+) -> Option<CollisionInfo> {
+    match (type_a, type_b) {
+        (CollisionType::Circle, CollisionType::Circle) => {
+            let radius_a = transform_a.width as f32 / 2.0;
+            let radius_b = transform_b.width as f32 / 2.0;
+            detect_circle_circle(
+                (transform_a.x_pos, transform_a.y_pos),
+                radius_a,
+                (transform_b.x_pos, transform_b.y_pos),
+                radius_b,
+            )
+        }
+        (CollisionType::Circle, CollisionType::Rectangle) => {
+            let radius = transform_a.width as f32 / 2.0;
+            detect_circle_rect(
+                (transform_a.x_pos, transform_a.y_pos),
+                radius,
+                (transform_b.x_pos, transform_b.y_pos),
+                transform_b.width as f32,
+                transform_b.height as f32,
+                transform_b.rotation,
+            ).map(CollisionInfo::flipped)
+        }
+        (CollisionType::Rectangle, CollisionType::Circle) => {
+            let radius = transform_b.width as f32 / 2.0;
+            detect_circle_rect(
+                (transform_b.x_pos, transform_b.y_pos),
+                radius,
+                (transform_a.x_pos, transform_a.y_pos),
+                transform_a.width as f32,
+                transform_a.height as f32,
+                transform_a.rotation,
+            )
+        }
+        (CollisionType::Circle, CollisionType::Polygon(_)) => {
+            let radius = transform_a.width as f32 / 2.0;
+            detect_circle_polygon(
+                (transform_a.x_pos, transform_a.y_pos),
+                radius,
+                &world_vertices(type_b, transform_b),
+            ).map(CollisionInfo::flipped)
+        }
+        (CollisionType::Polygon(_), CollisionType::Circle) => {
+            let radius = transform_b.width as f32 / 2.0;
+            detect_circle_polygon(
+                (transform_b.x_pos, transform_b.y_pos),
+                radius,
+                &world_vertices(type_a, transform_a),
+            )
+        }
+        (
+            CollisionType::Rectangle | CollisionType::Polygon(_),
+            CollisionType::Rectangle | CollisionType::Polygon(_),
+        ) => {
+            detect_polygon_polygon(&world_vertices(type_a, transform_a), &world_vertices(type_b, transform_b))
+        }
+        (CollisionType::Circle, CollisionType::Capsule) => {
+            let (start, end, capsule_radius) = capsule_segment(transform_b);
+            detect_circle_capsule(
+                (transform_a.x_pos, transform_a.y_pos),
+                transform_a.width as f32 / 2.0,
+                start,
+                end,
+                capsule_radius,
+            ).map(CollisionInfo::flipped)
         }
+        (CollisionType::Capsule, CollisionType::Circle) => {
+            let (start, end, capsule_radius) = capsule_segment(transform_a);
+            detect_circle_capsule(
+                (transform_b.x_pos, transform_b.y_pos),
+                transform_b.width as f32 / 2.0,
+                start,
+                end,
+                capsule_radius,
+            )
+        }
+        (CollisionType::Capsule, CollisionType::Capsule) => {
+            let (start_a, end_a, radius_a) = capsule_segment(transform_a);
+            let (start_b, end_b, radius_b) = capsule_segment(transform_b);
+            let (closest_a, closest_b) = closest_points_segments(start_a, end_a, start_b, end_b);
+            detect_circle_circle(closest_a, radius_a, closest_b, radius_b)
+        }
+        (CollisionType::Capsule, CollisionType::Rectangle | CollisionType::Polygon(_)) => {
+            let (start, end, radius) = capsule_segment(transform_a);
+            detect_capsule_polygon(start, end, radius, &world_vertices(type_b, transform_b))
+                .map(CollisionInfo::flipped)
+        }
+        (CollisionType::Rectangle | CollisionType::Polygon(_), CollisionType::Capsule) => {
+            let (start, end, radius) = capsule_segment(transform_b);
+            detect_capsule_polygon(start, end, radius, &world_vertices(type_a, transform_a))
+        }
+        // Split up by detect_shapes
+        (CollisionType::Compound(_) | CollisionType::Edge | CollisionType::Chain(_), _)
+        | (_, CollisionType::Compound(_) | CollisionType::Edge | CollisionType::Chain(_)) => None,
     }
 }
 
@@ -317,15 +1127,24 @@ fn detect_circle_circle(
     let min_distance = radius_a + radius_b;
 
     if distance < min_distance {
+        // Safety check for zero distance
+        if distance < 0.001 {
+            return None;
+        }
+
         // Calculate collision normal (direction from a to b)
         let normal_x = dx / distance;
         let normal_y = dy / distance;
         let penetration = min_distance - distance;
 
-        Some(CollisionInfo {
-            normal: (normal_x, normal_y),
+        // Contact halfway through the overlap
+        let contact_distance = radius_a - penetration / 2.0;
+
+        Some(CollisionInfo::single(
+            (normal_x, normal_y),
             penetration,
-        })
+            (pos_a.0 + normal_x * contact_distance, pos_a.1 + normal_y * contact_distance),
+        ))
     } else {
         None
     }
@@ -360,14 +1179,28 @@ fn detect_circle_rect(
     let dy_local = local_y - closest_y;
     let distance_sq = dx_local * dx_local + dy_local * dy_local;
 
+    // Centre inside the rectangle: push out through the nearest face
+    if distance_sq < 0.000001 {
+        let pen_x = half_w - local_x.abs();
+        let pen_y = half_h - local_y.abs();
+        let (normal_x_local, normal_y_local, closest_x, closest_y, depth) = if pen_x < pen_y {
+            let side = if local_x < 0.0 { -1.0 } else { 1.0 };
+            (side, 0.0, side * half_w, local_y, pen_x)
+        } else {
+            let side = if local_y < 0.0 { -1.0 } else { 1.0 };
+            (0.0, side, local_x, side * half_h, pen_y)
+        };
+
+        return Some(CollisionInfo::single(
+            (normal_x_local * cos - normal_y_local * sin, normal_x_local * sin + normal_y_local * cos),
+            radius + depth,
+            (rect_pos.0 + closest_x * cos - closest_y * sin, rect_pos.1 + closest_x * sin + closest_y * cos),
+        ));
+    }
+
     if distance_sq < radius * radius {
         let distance = distance_sq.sqrt();
 
-        // Safety check for zero distance
-        if distance < 0.001 {
-            return None; // Objects are exactly overlapping, skip this frame
-        }
-
         let normal_x_local = dx_local / distance;
         let normal_y_local = dy_local / distance;
 
@@ -375,21 +1208,369 @@ fn detect_circle_rect(
         let normal_x = normal_x_local * cos - normal_y_local * sin;
         let normal_y = normal_x_local * sin + normal_y_local * cos;
 
-        Some(CollisionInfo {
-            normal: (normal_x, normal_y),
-            penetration: radius - distance,
-        })
+        // Closest point on the rectangle, back in world space
+        let contact_x = rect_pos.0 + closest_x * cos - closest_y * sin;
+        let contact_y = rect_pos.1 + closest_x * sin + closest_y * cos;
+
+        Some(CollisionInfo::single(
+            (normal_x, normal_y),
+            radius - distance,
+            (contact_x, contact_y),
+        ))
     } else {
         None
     }
 }
 
 
+// Corners of a (rotated) rectangle in world space, counter-clockwise with +Y up
+fn rect_vertices(transform: &things::Transform) -> [(f32, f32); 4] {
+    let half_w = transform.width as f32 / 2.0;
+    let half_h = transform.height as f32 / 2.0;
+    let position = (transform.x_pos, transform.y_pos);
+
+    [(-half_w, -half_h), (half_w, -half_h), (half_w, half_h), (-half_w, half_h)]
+        .map(|corner| to_world(corner, position, transform.rotation))
+}
+
+// Vertices of a rectangle or polygon shape in world space
+fn world_vertices(collision_type: &CollisionType, transform: &things::Transform) -> Vec<(f32, f32)> {
+    match collision_type {
+        CollisionType::Rectangle => rect_vertices(transform).to_vec(),
+        CollisionType::Polygon(vertices) => vertices.iter()
+            .map(|&vertex| to_world(vertex, (transform.x_pos, transform.y_pos), transform.rotation))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Outward normal of the edge from `v1` to `v2` of a counter-clockwise polygon
+fn edge_normal(v1: (f32, f32), v2: (f32, f32)) -> (f32, f32) {
+    let edge = sub(v2, v1);
+    normalize((edge.1, -edge.0))
+}
+
+// Separating axis test over the edge normals of `poly_a`.
+// Returns the largest separation (negative while overlapping) and the edge it belongs to.
+fn find_max_separation(poly_a: &[(f32, f32)], poly_b: &[(f32, f32)]) -> (f32, usize) {
+    let mut best_separation = f32::MIN;
+    let mut best_edge = 0;
+
+    for i in 0..poly_a.len() {
+        let v1 = poly_a[i];
+        let normal = edge_normal(v1, poly_a[(i + 1) % poly_a.len()]);
+
+        // Deepest point of B behind this edge
+        let separation = poly_b.iter()
+            .map(|&v| dot(normal, sub(v, v1)))
+            .fold(f32::MAX, f32::min);
+
+        if separation > best_separation {
+            best_separation = separation;
+            best_edge = i;
+        }
+    }
+
+    (best_separation, best_edge)
+}
+
+// Circle against a convex polygon (world space). The normal points from the polygon to the circle.
+fn detect_circle_polygon(center: (f32, f32), radius: f32, poly: &[(f32, f32)]) -> Option<CollisionInfo> {
+    // Edge the centre is furthest in front of
+    let mut separation = f32::MIN;
+    let mut edge = 0;
+    for i in 0..poly.len() {
+        let normal = edge_normal(poly[i], poly[(i + 1) % poly.len()]);
+        let distance = dot(normal, sub(center, poly[i]));
+        if distance > separation {
+            separation = distance;
+            edge = i;
+        }
+    }
+
+    if separation > radius {
+        return None;
+    }
+
+    let v1 = poly[edge];
+    let v2 = poly[(edge + 1) % poly.len()];
+    let face_normal = edge_normal(v1, v2);
+
+    // Centre inside the polygon, push out through the nearest face
+    if separation < 0.0 {
+        return Some(CollisionInfo::single(
+            face_normal,
+            radius - separation,
+            sub(center, scale(face_normal, separation)),
+        ));
+    }
+
+    // Past one of the edge's ends, the closest feature is a corner
+    let edge_vector = sub(v2, v1);
+    let along = dot(sub(center, v1), edge_vector) / dot(edge_vector, edge_vector);
+    if !(0.0..=1.0).contains(&along) {
+        let corner = if along < 0.0 { v1 } else { v2 };
+        let offset = sub(center, corner);
+        let distance = length(offset);
+        if distance > radius || distance < 0.001 {
+            return None;
+        }
+
+        return Some(CollisionInfo::single(scale(offset, 1.0 / distance), radius - distance, corner));
+    }
+
+    Some(CollisionInfo::single(
+        face_normal,
+        radius - separation,
+        sub(center, scale(face_normal, separation)),
+    ))
+}
+
+// Circle against a capsule (world space). The normal points from the capsule to the circle.
+fn detect_circle_capsule(
+    center: (f32, f32),
+    radius: f32,
+    start: (f32, f32),
+    end: (f32, f32),
+    capsule_radius: f32,
+) -> Option<CollisionInfo> {
+    // Closest point on the core segment acts like a circle centre
+    let closest = closest_point_on_segment(center, start, end);
+    detect_circle_circle(closest, capsule_radius, center, radius)
+}
+
+fn point_in_polygon(point: (f32, f32), poly: &[(f32, f32)]) -> bool {
+    (0..poly.len()).all(|i| {
+        let normal = edge_normal(poly[i], poly[(i + 1) % poly.len()]);
+        dot(normal, sub(point, poly[i])) <= 0.0
+    })
+}
+
+// Capsule against a convex polygon (world space). The normal points from the polygon to the capsule.
+fn detect_capsule_polygon(
+    start: (f32, f32),
+    end: (f32, f32),
+    radius: f32,
+    poly: &[(f32, f32)],
+) -> Option<CollisionInfo> {
+    // Closest points between the core segment and the polygon outline
+    let mut distance = f32::MAX;
+    let mut on_segment = start;
+    let mut on_polygon = poly[0];
+    let mut edge = 0;
+    for i in 0..poly.len() {
+        let (closest_segment, closest_polygon) =
+            closest_points_segments(start, end, poly[i], poly[(i + 1) % poly.len()]);
+        let edge_distance = length(sub(closest_segment, closest_polygon));
+        if edge_distance < distance {
+            distance = edge_distance;
+            on_segment = closest_segment;
+            on_polygon = closest_polygon;
+            edge = i;
+        }
+    }
+
+    let core_inside = distance < 0.001 || point_in_polygon(start, poly) || point_in_polygon(end, poly);
+
+    if !core_inside {
+        if distance > radius {
+            return None;
+        }
+
+        let normal = scale(sub(on_segment, on_polygon), 1.0 / distance);
+
+        // Lying flat along a face, touch with both ends so it doesn't rock
+        let face_normal = edge_normal(poly[edge], poly[(edge + 1) % poly.len()]);
+        let axis = normalize(sub(end, start));
+        if dot(normal, face_normal) > 0.99
+            && dot(axis, face_normal).abs() < 0.05
+            && let Some(info) = capsule_face_contacts(start, end, radius, poly[edge], poly[(edge + 1) % poly.len()])
+        {
+            return Some(info);
+        }
+
+        return Some(CollisionInfo::single(normal, radius - distance, on_polygon));
+    }
+
+    // Core inside the polygon, push out through the face needing the least movement
+    let mut best_depth = f32::MAX;
+    let mut best_edge = 0;
+    for i in 0..poly.len() {
+        let v1 = poly[i];
+        let normal = edge_normal(v1, poly[(i + 1) % poly.len()]);
+        let deepest = dot(normal, sub(start, v1)).min(dot(normal, sub(end, v1)));
+        let depth = radius - deepest;
+        if depth < best_depth {
+            best_depth = depth;
+            best_edge = i;
+        }
+    }
+
+    let v1 = poly[best_edge];
+    let v2 = poly[(best_edge + 1) % poly.len()];
+    capsule_face_contacts(start, end, radius, v1, v2).or_else(|| {
+        // Core sticks out past the face's sides, use its deepest end
+        let normal = edge_normal(v1, v2);
+        let deepest = if dot(normal, sub(start, v1)) < dot(normal, sub(end, v1)) { start } else { end };
+        let depth = dot(normal, sub(deepest, v1));
+        Some(CollisionInfo::single(normal, radius - depth, sub(deepest, scale(normal, depth))))
+    })
+}
+
+// Contacts between a capsule and one polygon face (v1 to v2), up to one per end of the capsule
+fn capsule_face_contacts(
+    start: (f32, f32),
+    end: (f32, f32),
+    radius: f32,
+    v1: (f32, f32),
+    v2: (f32, f32),
+) -> Option<CollisionInfo> {
+    let normal = edge_normal(v1, v2);
+    let tangent = normalize(sub(v2, v1));
+    let clipped = clip_segment([start, end], negate(tangent), -dot(tangent, v1))
+        .and_then(|points| clip_segment(points, tangent, dot(tangent, v2)))?;
+
+    let mut contacts = [(0.0, 0.0); 2];
+    let mut depths = [0.0; 2];
+    let mut features = [0; 2];
+    let mut contact_count = 0;
+    let mut penetration: f32 = 0.0;
+
+    for (i, point) in clipped.into_iter().enumerate() {
+        let distance = dot(normal, sub(point, v1));
+        if distance < radius + CONTACT_SKIN {
+            contacts[contact_count] = sub(point, scale(normal, distance));
+            depths[contact_count] = radius - distance;
+            features[contact_count] = i as u32;
+            contact_count += 1;
+            penetration = penetration.max(radius - distance);
+        }
+    }
+
+    if contact_count == 0 || penetration <= 0.0 {
+        return None;
+    }
+
+    Some(CollisionInfo {
+        normal,
+        penetration,
+        contacts,
+        depths,
+        features,
+        contact_count,
+    })
+}
+
+// Keeps the part of a segment where dot(normal, p) <= offset
+fn clip_segment(points: [(f32, f32); 2], normal: (f32, f32), offset: f32) -> Option<[(f32, f32); 2]> {
+    let distance_1 = dot(normal, points[0]) - offset;
+    let distance_2 = dot(normal, points[1]) - offset;
+
+    if distance_1 <= 0.0 && distance_2 <= 0.0 {
+        return Some(points);
+    }
+    if distance_1 > 0.0 && distance_2 > 0.0 {
+        return None;
+    }
+
+    let t = distance_1 / (distance_1 - distance_2);
+    let intersection = add(points[0], scale(sub(points[1], points[0]), t));
+
+    if distance_1 > 0.0 {
+        Some([intersection, points[1]])
+    } else {
+        Some([points[0], intersection])
+    }
+}
+
+// SAT between two convex polygons (world space, counter-clockwise with +Y up).
+// The edge with the least overlap becomes the reference face, and the most facing edge of the
+// other polygon is clipped against it to get up to two contact points.
+fn detect_polygon_polygon(poly_a: &[(f32, f32)], poly_b: &[(f32, f32)]) -> Option<CollisionInfo> {
+    let (separation_a, edge_a) = find_max_separation(poly_a, poly_b);
+    if separation_a > CONTACT_SKIN {
+        return None;
+    }
+
+    let (separation_b, edge_b) = find_max_separation(poly_b, poly_a);
+    if separation_b > CONTACT_SKIN {
+        return None;
+    }
+
+    // Prefer A as the reference so the choice doesn't flicker between frames
+    let flip = separation_b > 0.95 * separation_a + 0.01;
+    let (reference, incident, reference_edge) = if flip {
+        (poly_b, poly_a, edge_b)
+    } else {
+        (poly_a, poly_b, edge_a)
+    };
+
+    let v1 = reference[reference_edge];
+    let v2 = reference[(reference_edge + 1) % reference.len()];
+    let reference_normal = edge_normal(v1, v2);
+
+    // Incident edge: the one facing most against the reference normal
+    let incident_edge = (0..incident.len())
+        .min_by(|&i, &j| {
+            let normal_i = edge_normal(incident[i], incident[(i + 1) % incident.len()]);
+            let normal_j = edge_normal(incident[j], incident[(j + 1) % incident.len()]);
+            dot(normal_i, reference_normal).total_cmp(&dot(normal_j, reference_normal))
+        })
+        .unwrap_or(0);
+    let incident_points = [incident[incident_edge], incident[(incident_edge + 1) % incident.len()]];
+
+    // Clip the incident edge to the sides of the reference edge
+    let tangent = normalize(sub(v2, v1));
+    let clipped = clip_segment(incident_points, negate(tangent), -dot(tangent, v1))
+        .and_then(|points| clip_segment(points, tangent, dot(tangent, v2)))?;
+
+    // Keep the points that are behind the reference face
+    let face_offset = dot(reference_normal, v1);
+    let mut contacts = [(0.0, 0.0); 2];
+    let mut depths = [0.0; 2];
+    let mut features = [0; 2];
+    let mut contact_count = 0;
+    let mut penetration = f32::MIN;
+
+    for (i, point) in clipped.into_iter().enumerate() {
+        let depth = face_offset - dot(reference_normal, point);
+        if depth >= -CONTACT_SKIN {
+            // Contact halfway between the two surfaces
+            contacts[contact_count] = add(point, scale(reference_normal, depth / 2.0));
+            depths[contact_count] = depth;
+            // Reference and incident edges, and which end of the clipped edge
+            features[contact_count] = (flip as u32) << 24
+                | (reference_edge as u32 & 0xff) << 16
+                | (incident_edge as u32 & 0xff) << 8
+                | i as u32;
+            contact_count += 1;
+            penetration = penetration.max(depth);
+        }
+    }
+
+    if contact_count == 0 {
+        return None;
+    }
+
+    // The reference normal points away from the reference polygon
+    let normal = if flip { negate(reference_normal) } else { reference_normal };
+
+    Some(CollisionInfo {
+        normal,
+        penetration,
+        contacts,
+        depths,
+        features,
+        contact_count,
+    })
+}
+
 
 #[derive(Default)]
 pub struct Space {
     counter: AtomicU16,
     pub(crate) things: HashMap<Id, Thing>,
+    pub(crate) visuals: HashMap<Id, things::Transform>, // Where things without a shape are drawn
 }
 
 impl Space {
@@ -397,36 +1578,43 @@ impl Space {
         self.counter.fetch_add(1, Ordering::Relaxed)
     }
 
-    pub(crate) fn register(&mut self,
-                           init_transform: things::Transform,
-                           sprite: Sprite,
-                           mut color: Option<(u8, u8, u8)>,
-                           dynamics: Option<Dynamics>,
-                           restitution: f32,
-                           collision_space: &mut CollisionSpace
-    ) -> Id {
+    pub fn register(&mut self,
+                    mut init_transform: things::Transform,
+                    mut sprite: Sprite,
+                    color: Option<(u8, u8, u8)>,
+                    dynamics: Option<Dynamics>,
+                    restitution: f32,
+                    collision_space: &mut CollisionSpace
+    ) -> Result<Id, InvalidSprite> {
+        sprite.validate()?;
         let new_id = self.gen_id();
 
-        if let None = color {
-            color = Some((0,0,0));
-        }
+        // Shapes turn around their position, so move it onto the centroid.
+        // The outline stays where it was in the world.
+        let centroid = centre_sprite(&mut sprite);
+        let offset = rotate(centroid, init_transform.rotation);
+        init_transform.x_pos += offset.0;
+        init_transform.y_pos += offset.1;
 
-        let dynamic = if let Some(_) = dynamics { true } else { false };
+        let dynamic = dynamics.is_some();
 
         let new_thing = Thing {
             id: new_id,
             sprite,
-            color: color.unwrap(),
+            color: color.unwrap_or((0, 0, 0)),
             dynamic,
         };
 
-        if matches!(dynamics.unwrap(), Dynamics::Dynamic | Dynamics::Kinematic) {
-            collision_space.register(new_thing.id, init_transform, CollisionType::from(&new_thing.sprite), restitution, dynamics.unwrap());
+        // Without dynamics (or with Dynamics::None) it's only drawn, where it was put
+        if let Some(dynamics @ (Dynamics::Dynamic | Dynamics::Kinematic)) = dynamics {
+            collision_space.register(new_thing.id, init_transform, CollisionType::from(&new_thing.sprite), restitution, dynamics);
+        } else {
+            self.visuals.insert(new_thing.id, init_transform);
         }
 
         self.things.insert(new_thing.id, new_thing);
 
-        new_id
+        Ok(new_id)
     }
 }
 
@@ -435,4 +1623,156 @@ fn print_debug(message: &str) {
     if DEBUG {
         println!("{}", message);
     }
-}
\ No newline at end of file
+}
+// Whether gravity turned or changed size enough to wake sleeping shapes
+fn gravity_changed(old: (f32, f32), new: (f32, f32)) -> bool {
+    let old_magnitude = length(old);
+    let new_magnitude = length(new);
+    if old_magnitude == 0.0 || new_magnitude == 0.0 {
+        return old_magnitude != new_magnitude;
+    }
+
+    let turned = cross(old, new).atan2(dot(old, new)).abs().to_degrees();
+    let resized = (new_magnitude - old_magnitude).abs() / old_magnitude;
+    turned > GRAVITY_WAKE_ANGLE || resized > GRAVITY_WAKE_RATIO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-3;
+
+    fn square(half: f32) -> CollisionType {
+        CollisionType::Polygon(vec![(-half, -half), (half, -half), (half, half), (-half, half)])
+    }
+
+    fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(length(sub(actual, expected)) < EPSILON, "got {:?}, expected {:?}", actual, expected);
+    }
+
+    #[test]
+    fn box_box_overlap_along_x() {
+        let a = things::Transform::new(0.0, 0.0, 100, 100, 0.0);
+        let b = things::Transform::new(90.0, 10.0, 100, 100, 0.0);
+        let info = detect_pair(&CollisionType::Rectangle, &a, &CollisionType::Rectangle, &b).unwrap();
+
+        assert_near(info.normal, (1.0, 0.0));
+        assert!((info.penetration - 10.0).abs() < EPSILON);
+        assert_eq!(info.contacts().len(), 2);
+        for &depth in info.depths() {
+            assert!((depth - 10.0).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn box_box_normal_points_from_a_to_b() {
+        let a = things::Transform::new(0.0, 100.0, 100, 20, 0.0);
+        let b = things::Transform::new(5.0, 82.0, 40, 20, 0.0);
+        let info = detect_pair(&CollisionType::Rectangle, &a, &CollisionType::Rectangle, &b).unwrap();
+
+        assert_near(info.normal, (0.0, -1.0));
+        assert!((info.penetration - 2.0).abs() < EPSILON);
+        assert_eq!(info.contacts().len(), 2);
+
+        let flipped = detect_pair(&CollisionType::Rectangle, &b, &CollisionType::Rectangle, &a).unwrap();
+        assert_near(flipped.normal, (0.0, 1.0));
+    }
+
+    #[test]
+    fn separated_boxes_dont_collide() {
+        let a = things::Transform::new(0.0, 0.0, 100, 100, 45.0);
+        let b = things::Transform::new(150.0, 0.0, 100, 100, 0.0);
+        assert!(detect_pair(&CollisionType::Rectangle, &a, &CollisionType::Rectangle, &b).is_none());
+    }
+
+    #[test]
+    fn circle_polygon_single_contact() {
+        let circle = things::Transform::new(0.0, -28.0, 20, 20, 0.0);
+        let polygon = things::Transform::new(0.0, 0.0, 0, 0, 0.0);
+        let info = detect_pair(&CollisionType::Circle, &circle, &square(20.0), &polygon).unwrap();
+
+        assert_near(info.normal, (0.0, 1.0));
+        assert!((info.penetration - 2.0).abs() < EPSILON);
+        assert_eq!(info.contacts().len(), 1);
+
+        let flipped = detect_pair(&square(20.0), &polygon, &CollisionType::Circle, &circle).unwrap();
+        assert_near(flipped.normal, (0.0, -1.0));
+    }
+
+    #[test]
+    fn capsule_lying_on_polygon_touches_along_its_side() {
+        // 60 long with a radius of 10, lying flat on a 40 wide square
+        let capsule = things::Transform::new(0.0, -28.0, 60, 20, 0.0);
+        let polygon = things::Transform::new(0.0, 0.0, 0, 0, 0.0);
+        let info = detect_pair(&CollisionType::Capsule, &capsule, &square(20.0), &polygon).unwrap();
+
+        assert_near(info.normal, (0.0, 1.0));
+        assert!((info.penetration - 2.0).abs() < EPSILON);
+        assert_eq!(info.contacts().len(), 2);
+        for &depth in info.depths() {
+            assert!((depth - 2.0).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn capsule_end_on_polygon_touches_at_one_point() {
+        let capsule = things::Transform::new(0.0, -48.0, 60, 20, 90.0);
+        let polygon = things::Transform::new(0.0, 0.0, 0, 0, 0.0);
+        let info = detect_pair(&CollisionType::Capsule, &capsule, &square(20.0), &polygon).unwrap();
+
+        assert_near(info.normal, (0.0, 1.0));
+        assert!((info.penetration - 2.0).abs() < EPSILON);
+        assert_eq!(info.contacts().len(), 1);
+    }
+
+    #[test]
+    fn bullet_hits_thin_wall_it_would_pass_through() {
+        // A 10 px bullet crossing 400 px in one step, against a 4 px wall
+        let bullet = Shape::new(things::Transform::new(0.0, 0.0, 10, 10, 0.0), CollisionType::Circle, Dynamics::Dynamic, 0.0);
+        let wall = Shape::new(things::Transform::new(0.0, 0.0, 4, 200, 0.0), CollisionType::Rectangle, Dynamics::Kinematic, 0.0);
+        let (toi, (x_pos, y_pos, _)) = time_of_impact(&bullet, (-200.0, 0.0, 0.0), (200.0, 0.0, 0.0), &wall).unwrap();
+
+        // Stopped just touching the near face, its radius plus half the wall away
+        assert!((toi - 193.0 / 400.0).abs() < 1e-3, "toi {}", toi);
+        assert!((x_pos + 7.0).abs() < 0.1, "x {}", x_pos);
+        assert_eq!(y_pos, 0.0);
+    }
+
+    #[test]
+    fn bullet_passing_beside_the_wall_has_no_impact() {
+        let bullet = Shape::new(things::Transform::new(0.0, 0.0, 10, 10, 0.0), CollisionType::Circle, Dynamics::Dynamic, 0.0);
+        let wall = Shape::new(things::Transform::new(0.0, 0.0, 4, 200, 0.0), CollisionType::Rectangle, Dynamics::Kinematic, 0.0);
+
+        assert!(time_of_impact(&bullet, (-200.0, 120.0, 0.0), (200.0, 120.0, 0.0), &wall).is_none());
+    }
+
+    #[test]
+    fn kinematic_target_turns_the_short_way_round() {
+        let mut space = CollisionSpace::default();
+        space.register(0, things::Transform::new(0.0, 0.0, 100, 10, 350.0), CollisionType::Rectangle, 0.0, Dynamics::Kinematic);
+        space.register(1, things::Transform::new(0.0, 100.0, 100, 10, 10.0), CollisionType::Rectangle, 0.0, Dynamics::Kinematic);
+        space.shapes.get_mut(&0).unwrap().set_target_pose(0.0, 0.0, 10.0);
+        space.shapes.get_mut(&1).unwrap().set_target_pose(0.0, 100.0, -170.0);
+        space.drive_kinematics(FIXED_TIMESTEP);
+
+        assert!((space.shapes[&0].transform.angular_vel - 20.0 / FIXED_TIMESTEP).abs() < 1.0);
+        assert!((space.shapes[&1].transform.angular_vel - 180.0 / FIXED_TIMESTEP).abs() < 1.0);
+    }
+
+    #[test]
+    fn bad_frame_times_are_ignored() {
+        let mut space = CollisionSpace::default();
+        space.register(0, things::Transform::new(0.0, 0.0, 20, 20, 0.0), CollisionType::Rectangle, 0.0, Dynamics::Dynamic);
+
+        for dt in [f32::NAN, f32::INFINITY, -1.0, 0.0] {
+            space.update(dt);
+        }
+        assert_eq!(space.accumulator, 0.0);
+        assert_eq!(space.shapes[&0].transform.y_pos, 0.0);
+
+        // Still steps on the next good frame
+        space.update(FIXED_TIMESTEP);
+        assert!(space.shapes[&0].transform.y_pos > 0.0);
+    }
+}
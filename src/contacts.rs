@@ -0,0 +1,115 @@
+//! Contact cache: every contact point between two bodies, remembered from one
+//! substep to the next. Contacts are told apart by the body pair plus a feature
+//! number from the narrowphase (which corner, edge, child or chain segment made
+//! them), so the same corner resting on the same face is the same contact for as
+//! long as it lasts.
+
+use std::collections::HashMap;
+
+use crate::things::{Dynamics, Shape};
+use crate::Id;
+
+/// Tells one contact point apart from the others.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContactKey {
+    pub id_a: Id,
+    pub id_b: Id,
+    /// Which corner, edge, child or chain segment made the contact
+    pub feature: u32,
+}
+
+/// Whether a contact just started, see [`Contact::status`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ContactStatus {
+    /// Started touching during the last step
+    New,
+    /// Was already touching the step before
+    Ongoing,
+}
+
+/// One point where two bodies touch.
+#[derive(Debug, Clone)]
+pub struct Contact {
+    /// World space
+    pub position: (f32, f32),
+    /// From A towards B
+    pub normal: (f32, f32),
+    /// Overlap, negative while the faces are only just apart
+    pub depth: f32,
+    /// Accumulated by the solver, carried into the next solve
+    pub normal_impulse: f32,
+    /// Friction, accumulated like `normal_impulse`
+    pub tangent_impulse: f32,
+    /// Steps it has lasted, 0 in the step it started
+    pub age: u32,
+}
+
+impl Contact {
+    /// New in the step it started, ongoing after that
+    pub fn status(&self) -> ContactStatus {
+        if self.age == 0 {
+            ContactStatus::New
+        } else {
+            ContactStatus::Ongoing
+        }
+    }
+}
+
+/// Every contact from the last substep, read through
+/// [`World::contacts`](crate::World::contacts).
+#[derive(Default)]
+pub struct ContactCache {
+    contacts: HashMap<ContactKey, Contact>,
+}
+
+impl ContactCache {
+    /// The contact with this key, if it's still touching
+    pub fn get(&self, key: &ContactKey) -> Option<&Contact> {
+        self.contacts.get(key)
+    }
+
+    /// All contacts, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&ContactKey, &Contact)> {
+        self.contacts.iter()
+    }
+
+    /// Contacts involving `id`, from either side
+    pub fn touching(&self, id: Id) -> impl Iterator<Item = (&ContactKey, &Contact)> {
+        self.contacts.iter().filter(move |(key, _)| key.id_a == id || key.id_b == id)
+    }
+
+    /// Number of contact points, not touching pairs
+    pub fn len(&self) -> usize {
+        self.contacts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+
+    // Everything still here has made it into another step
+    pub(crate) fn begin_step(&mut self) {
+        for contact in self.contacts.values_mut() {
+            contact.age += 1;
+        }
+    }
+
+    // Replaces the cache with this substep's contacts, keeping the age of the
+    // ones that were already there. Contacts between sleeping bodies aren't
+    // checked while they sleep, so they're kept as they are for when they wake.
+    pub(crate) fn update(&mut self, contacts: Vec<(ContactKey, Contact)>, shapes: &HashMap<Id, Shape>) {
+        let mut previous = std::mem::take(&mut self.contacts);
+
+        for (key, mut contact) in contacts {
+            if let Some(old) = previous.remove(&key) {
+                contact.age = old.age;
+            }
+            self.contacts.insert(key, contact);
+        }
+
+        let asleep = |id: &Id| shapes.get(id)
+            .is_some_and(|shape| shape.sleeping || !matches!(shape.dynamics, Dynamics::Dynamic));
+        self.contacts.extend(previous.into_iter()
+            .filter(|(key, _)| asleep(&key.id_a) && asleep(&key.id_b)));
+    }
+}
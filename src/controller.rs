@@ -1,7 +1,13 @@
+#[cfg(feature = "controller-serial")]
 use serialport::{SerialPort, SerialPortType, DataBits, FlowControl, Parity, StopBits};
+#[cfg(feature = "controller-serial")]
 use std::time::Duration;
+#[cfg(feature = "controller-serial")]
 use std::thread;
 
+// Platform rotation (degrees) at full tilt
+pub const MAX_TILT_DEGREES: f32 = 65.0;
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Input {
     pub x: f32,
@@ -9,6 +15,36 @@ pub struct Input {
     pub z: f32,
 }
 
+impl Input {
+    // Parses one line of the Pico's CSV output ("x,y,z" raw readings)
+    pub fn from_csv_line(line: &str) -> Option<Input> {
+        let parts: Vec<&str> = line.trim().split(',').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+
+        if let (Ok(x), Ok(y), Ok(z)) = (
+            parts[0].trim().parse::<i16>(),
+            parts[1].trim().parse::<i16>(),
+            parts[2].trim().parse::<i16>(),
+        ) {
+            Some(Input {
+                x: x as f32 / 256.0,
+                y: y as f32 / 256.0,
+                z: z as f32 / 256.0,
+            })
+        } else {
+            None
+        }
+    }
+
+    // Rotation for a tilting platform, rounded to filter out sensor noise
+    pub fn tilt_degrees(&self) -> f32 {
+        ((self.x * 100.0).round() / 100.0) * MAX_TILT_DEGREES
+    }
+}
+
+#[cfg(feature = "controller-serial")]
 pub struct AccelerometerReader {
     port: Box<dyn SerialPort>,
     smoothed_x: f32,
@@ -18,6 +54,7 @@ pub struct AccelerometerReader {
     line_buffer: String,
 }
 
+#[cfg(feature = "controller-serial")]
 impl AccelerometerReader {
     pub fn new(port_name: &str, baud_rate: u32, smoothing: f32) -> Result<Self, Box<dyn std::error::Error>> {
         let mut port = serialport::new(port_name, baud_rate)
@@ -57,32 +94,19 @@ impl AccelerometerReader {
             match self.port.read(&mut byte_buf) {
                 Ok(1) => {
                     if byte_buf[0] == b'\n' && !self.line_buffer.is_empty() {
-                        let parts: Vec<&str> = self.line_buffer.trim().split(',').collect();
-                        if parts.len() == 3 {
-                            if let (Ok(x), Ok(y), Ok(z)) = (
-                                parts[0].parse::<i16>(),
-                                parts[1].parse::<i16>(),
-                                parts[2].parse::<i16>(),
-                            ) {
-                                let input = Input {
-                                    x: x as f32 / 256.0,
-                                    y: y as f32 / 256.0,
-                                    z: z as f32 / 256.0,
-                                };
-
-                                // Update smoothed values
-                                self.smoothed_x = self.smoothed_x * self.smoothing + input.x * (1.0 - self.smoothing);
-                                self.smoothed_y = self.smoothed_y * self.smoothing + input.y * (1.0 - self.smoothing);
-                                self.smoothed_z = self.smoothed_z * self.smoothing + input.z * (1.0 - self.smoothing);
-
-                                self.line_buffer.clear();
-
-                                return Some(Input {
-                                    x: self.smoothed_x,
-                                    y: self.smoothed_y,
-                                    z: self.smoothed_z,
-                                });
-                            }
+                        if let Some(input) = Input::from_csv_line(&self.line_buffer) {
+                            // Update smoothed values
+                            self.smoothed_x = self.smoothed_x * self.smoothing + input.x * (1.0 - self.smoothing);
+                            self.smoothed_y = self.smoothed_y * self.smoothing + input.y * (1.0 - self.smoothing);
+                            self.smoothed_z = self.smoothed_z * self.smoothing + input.z * (1.0 - self.smoothing);
+
+                            self.line_buffer.clear();
+
+                            return Some(Input {
+                                x: self.smoothed_x,
+                                y: self.smoothed_y,
+                                z: self.smoothed_z,
+                            });
                         }
                         self.line_buffer.clear();
                     } else if byte_buf[0] != b'\r' && byte_buf[0].is_ascii() {
@@ -98,6 +122,7 @@ impl AccelerometerReader {
     }
 }
 
+#[cfg(feature = "controller-serial")]
 pub fn find_pico_port() -> Option<String> {
     let ports = serialport::available_ports().ok()?;
     for port in ports {
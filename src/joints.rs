@@ -0,0 +1,706 @@
+//! Joints: constraints between two bodies, or between a body and a fixed point
+//! in the world. They're solved by the contact solver alongside the contacts,
+//! as impulses that cancel the relative motion the joint doesn't allow.
+
+use std::collections::HashMap;
+
+use crate::math::{cross, dot, inverse, length, normalize, rotate, scale, sub, transform, Mat2};
+use crate::solver::POSITION_CORRECTION;
+use crate::things::Shape;
+use crate::Id;
+
+/// Handle for a joint added to the world.
+pub type JointId = u32;
+
+/// What a joint allows the two bodies to do relative to each other.
+#[derive(Debug, Copy, Clone)]
+pub enum JointKind {
+    /// Rigid rod, the anchors stay exactly `length` apart
+    Distance { length: f32 },
+    /// Slack until the anchors are `max_length` apart
+    Rope { max_length: f32 },
+    /// Pulls or pushes the anchors towards `rest_length` apart. Stiffness is force
+    /// per px stretched, damping is force per px/s of stretching.
+    Spring { rest_length: f32, stiffness: f32, damping: f32 },
+    /// Pin through both anchors, B turns freely relative to A
+    Revolute {
+        /// Lowest and highest joint angle (degrees), B's rotation relative to A's
+        limits: Option<(f32, f32)>,
+        motor: Option<Motor>,
+    },
+    /// B's anchor slides along a line through A's, without B turning relative to A
+    Prismatic {
+        /// Direction of the line, local to A
+        axis: (f32, f32),
+        /// Lowest and highest translation along the axis (px)
+        limits: Option<(f32, f32)>,
+        /// Speed in px/s, `max_torque` is a force
+        motor: Option<Motor>,
+    },
+    /// B's anchor rides a suspension spring along a line through A's, and B
+    /// turns freely: a wheel (B) on a car (A)
+    Wheel {
+        /// Direction of the suspension, local to A
+        axis: (f32, f32),
+        /// Spring along the axis, like `Spring`
+        stiffness: f32,
+        damping: f32,
+        /// Turns the wheel
+        motor: Option<Motor>,
+    },
+    /// Holds B at the reference angle with the anchors together, as if the
+    /// two were one body
+    Weld,
+}
+
+/// Drives B relative to A at a set speed, as hard as `max_torque` allows.
+#[derive(Debug, Copy, Clone)]
+pub struct Motor {
+    /// Degrees per second, or px/s for sliding joints. Positive turns B in
+    /// +rotation (clockwise on screen) relative to A.
+    pub speed: f32,
+    /// A force for sliding joints
+    pub max_torque: f32,
+}
+
+/// A constraint between body A and body B, or between body A and the world.
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub body_a: Id,
+    /// `None` pins body A to a point in the world
+    pub body_b: Option<Id>,
+    /// Local to body A
+    pub anchor_a: (f32, f32),
+    /// Local to body B, or world space without one
+    pub anchor_b: (f32, f32),
+    pub kind: JointKind,
+    /// Whether the two bodies still collide with each other
+    pub collide_connected: bool,
+    /// B's rotation relative to A's where the joint angle is 0
+    pub reference_angle: f32,
+    /// Pulling harder than this breaks the joint off
+    pub break_force: Option<f32>,
+    /// Turning harder than this breaks the joint off
+    pub break_torque: Option<f32>,
+    state: JointState,
+}
+
+/// Something that happened to a joint during the last step.
+#[derive(Debug, Clone)]
+pub enum JointEvent {
+    /// Pulled past its break force or torque, and taken out of the world
+    Broken { id: JointId, joint: Joint },
+}
+
+// Worked out at the start of each solve, apart from the impulses which are
+// kept for warm starting the next one
+#[derive(Debug, Default, Copy, Clone)]
+struct JointState {
+    point_a: (f32, f32), // Anchors in world space
+    point_b: (f32, f32),
+
+    // Along the axis: distance, rope and spring joints, and the sliding
+    // direction of prismatic and wheel joints
+    axis: (f32, f32), // From A's anchor towards B's
+    axial_mass: f32,
+    bias: f32, // Separating speed to aim for along the axis
+    softness: f32, // How far a spring gives per unit of impulse, 0 for rigid joints
+    impulse: f32, // Positive pushes the anchors apart
+
+    // The two directions a joint locks: x and y for pins, across the axis and
+    // turning for prismatic joints, only across the axis for wheels
+    point_mass: Mat2, // Inverse of the effective mass matrix
+    point_bias: (f32, f32), // Relative velocity to aim for, closing any gap
+    point_impulse: (f32, f32),
+
+    // Limits and motors, turning or along the axis
+    angular_mass: f32,
+    lower_bias: f32, // Speeds each limit allows towards it
+    upper_bias: f32,
+    max_motor_impulse: f32,
+    motor_impulse: f32, // Drives B, the opposite drives A
+    lower_impulse: f32,
+    upper_impulse: f32,
+
+    // Holding the angle, for welds
+    lock_bias: f32,
+    lock_impulse: f32, // Turns B, the opposite turns A
+}
+
+impl Joint {
+    /// A joint of any kind, not breakable and with the bodies not colliding with
+    /// each other. The constructors below cover the usual setups.
+    pub fn new(body_a: Id, anchor_a: (f32, f32), body_b: Option<Id>, anchor_b: (f32, f32), kind: JointKind) -> Self {
+        Joint {
+            body_a,
+            body_b,
+            anchor_a,
+            anchor_b,
+            kind,
+            collide_connected: false,
+            reference_angle: 0.0,
+            break_force: None,
+            break_torque: None,
+            state: JointState::default(),
+        }
+    }
+
+    /// Rod keeping the anchors `length` px apart
+    pub fn distance(body_a: Id, anchor_a: (f32, f32), body_b: Option<Id>, anchor_b: (f32, f32), length: f32) -> Self {
+        Self::new(body_a, anchor_a, body_b, anchor_b, JointKind::Distance { length })
+    }
+
+    /// Rope letting the anchors get at most `max_length` px apart
+    pub fn rope(body_a: Id, anchor_a: (f32, f32), body_b: Option<Id>, anchor_b: (f32, f32), max_length: f32) -> Self {
+        Self::new(body_a, anchor_a, body_b, anchor_b, JointKind::Rope { max_length })
+    }
+
+    /// Spring between the anchors, see [`JointKind::Spring`]
+    pub fn spring(
+        body_a: Id,
+        anchor_a: (f32, f32),
+        body_b: Option<Id>,
+        anchor_b: (f32, f32),
+        rest_length: f32,
+        stiffness: f32,
+        damping: f32,
+    ) -> Self {
+        Self::new(body_a, anchor_a, body_b, anchor_b, JointKind::Spring { rest_length, stiffness, damping })
+    }
+
+    /// A hinge with no limits or motor, add them with set_limits and set_motor.
+    ///
+    /// The joint angle is B's rotation minus A's, less the reference angle, so
+    /// limits and motor speeds are B turning relative to A. Positive is +rotation,
+    /// clockwise on screen since +Y points down. With the world as B the angle is
+    /// minus A's rotation instead, and everything flips: a positive motor speed
+    /// turns A anticlockwise, and the limits bound -rotation.
+    pub fn revolute(body_a: Id, anchor_a: (f32, f32), body_b: Option<Id>, anchor_b: (f32, f32)) -> Self {
+        Self::new(body_a, anchor_a, body_b, anchor_b, JointKind::Revolute { limits: None, motor: None })
+    }
+
+    /// A slider along `axis` (local to A) with no limits or motor. Like the other
+    /// joints it measures B relative to A, so with the world as B a body moving
+    /// along +axis has a falling translation and a negative motor speed.
+    pub fn prismatic(body_a: Id, anchor_a: (f32, f32), body_b: Option<Id>, anchor_b: (f32, f32), axis: (f32, f32)) -> Self {
+        Self::new(body_a, anchor_a, body_b, anchor_b, JointKind::Prismatic { axis, limits: None, motor: None })
+    }
+
+    /// Wheel B on A, sprung along `axis` (local to A), with no motor
+    pub fn wheel(
+        body_a: Id,
+        anchor_a: (f32, f32),
+        body_b: Id,
+        anchor_b: (f32, f32),
+        axis: (f32, f32),
+        stiffness: f32,
+        damping: f32,
+    ) -> Self {
+        Self::new(body_a, anchor_a, Some(body_b), anchor_b, JointKind::Wheel { axis, stiffness, damping, motor: None })
+    }
+
+    /// B stays at `reference_angle` to A, usually how they're turned when welded
+    pub fn weld(body_a: Id, anchor_a: (f32, f32), body_b: Option<Id>, anchor_b: (f32, f32), reference_angle: f32) -> Self {
+        Joint { reference_angle, ..Self::new(body_a, anchor_a, body_b, anchor_b, JointKind::Weld) }
+    }
+
+    /// Lowest and highest joint angle (degrees), or translation (px) for sliders.
+    /// Both are B relative to A, see [`Joint::revolute`] for which way is positive.
+    /// Does nothing for joints without limits.
+    pub fn set_limits(&mut self, lower: f32, upper: f32) {
+        if let JointKind::Revolute { limits, .. } | JointKind::Prismatic { limits, .. } = &mut self.kind {
+            *limits = Some((lower, upper));
+        }
+    }
+
+    /// Drives the joint at `speed`, see [`Motor`]. A positive speed turns B in +rotation
+    /// relative to A, clockwise on screen, or A anticlockwise with the world as B.
+    /// Does nothing for joints without a motor.
+    pub fn set_motor(&mut self, speed: f32, max_torque: f32) {
+        if let JointKind::Revolute { motor, .. } | JointKind::Prismatic { motor, .. } | JointKind::Wheel { motor, .. } = &mut self.kind {
+            *motor = Some(Motor { speed, max_torque });
+        }
+    }
+
+    /// The joint's motor, if it has one set
+    pub fn motor(&self) -> Option<&Motor> {
+        match &self.kind {
+            JointKind::Revolute { motor, .. } | JointKind::Prismatic { motor, .. } | JointKind::Wheel { motor, .. } => motor.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The joint's motor, to change its speed or strength while it runs
+    pub fn motor_mut(&mut self) -> Option<&mut Motor> {
+        match &mut self.kind {
+            JointKind::Revolute { motor, .. } | JointKind::Prismatic { motor, .. } | JointKind::Wheel { motor, .. } => motor.as_mut(),
+            _ => None,
+        }
+    }
+
+    /// Impulse the joint applied over the last substep, along its axis: the rod,
+    /// rope or spring of distance joints, the motor and limits of prismatic ones,
+    /// the suspension of wheels. Positive pushes B's anchor away from A's, or along
+    /// the axis for sliders. Always 0 for revolute and weld joints, which have no axis.
+    pub fn impulse(&self) -> f32 {
+        let state = &self.state;
+        match self.kind {
+            JointKind::Prismatic { .. } => state.motor_impulse + state.lower_impulse - state.upper_impulse,
+            _ => state.impulse,
+        }
+    }
+
+    // Both ends in world space
+    pub(crate) fn anchors(&self, shapes: &HashMap<Id, Shape>) -> ((f32, f32), (f32, f32)) {
+        (
+            world_anchor(shapes, Some(self.body_a), self.anchor_a),
+            world_anchor(shapes, self.body_b, self.anchor_b),
+        )
+    }
+
+    // B's rotation relative to A's (degrees), from the reference angle
+    pub(crate) fn angle(&self, shapes: &HashMap<Id, Shape>) -> f32 {
+        rotation(shapes, self.body_b) - rotation(shapes, Some(self.body_a)) - self.reference_angle
+    }
+
+    // How far B's anchor is along a slider's axis from A's (px), 0 for other joints
+    pub(crate) fn translation(&self, shapes: &HashMap<Id, Shape>) -> f32 {
+        match self.kind {
+            JointKind::Prismatic { axis, .. } | JointKind::Wheel { axis, .. } => {
+                let (point_a, point_b) = self.anchors(shapes);
+                dot(sub(point_b, point_a), world_axis(shapes, self.body_a, axis))
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub(crate) fn bodies(&self) -> impl Iterator<Item = Id> {
+        [Some(self.body_a), self.body_b].into_iter().flatten()
+    }
+
+    pub(crate) fn prepare(&mut self, shapes: &HashMap<Id, Shape>, dt: f32) {
+        let (point_a, point_b) = self.anchors(shapes);
+        let angle = self.angle(shapes);
+        let translation = self.translation(shapes);
+        let shape_a = body(shapes, Some(self.body_a));
+        let shape_b = body(shapes, self.body_b);
+
+        let state = &mut self.state;
+        state.point_a = point_a;
+        state.point_b = point_b;
+
+        let angular_mass = inv_inertia(shape_a) + inv_inertia(shape_b);
+        state.angular_mass = if angular_mass > 0.0 { 1.0 / angular_mass } else { 0.0 };
+
+        match self.kind {
+            JointKind::Distance { .. } | JointKind::Rope { .. } | JointKind::Spring { .. } => {
+                let offset = sub(point_b, point_a);
+                let current = length(offset);
+                let axis = if current > 0.0 { scale(offset, 1.0 / current) } else { (0.0, 0.0) };
+                state.axis = axis;
+
+                let mut axial_mass = axial_inv_mass(shape_a, point_a, axis) + axial_inv_mass(shape_b, point_b, axis);
+                state.softness = 0.0;
+
+                state.bias = match self.kind {
+                    JointKind::Spring { rest_length, stiffness, damping } => {
+                        spring_bias(state, &mut axial_mass, current - rest_length, stiffness, damping, dt)
+                    }
+                    JointKind::Rope { max_length } if current < max_length => {
+                        // Slack: free to close the gap within this substep, but no further
+                        state.impulse = 0.0;
+                        (max_length - current) / dt
+                    }
+                    JointKind::Rope { max_length } => -POSITION_CORRECTION / dt * (current - max_length),
+                    JointKind::Distance { length } => -POSITION_CORRECTION / dt * (current - length),
+                    _ => 0.0,
+                };
+                state.axial_mass = if axial_mass > 0.0 { 1.0 / axial_mass } else { 0.0 };
+            }
+            JointKind::Revolute { limits, motor } => {
+                state.point_mass = pin_mass(shape_a, point_a, shape_b, point_b);
+                state.point_bias = scale(sub(point_b, point_a), -POSITION_CORRECTION / dt);
+
+                prepare_motor(state, motor, dt);
+                let gaps = limits.map(|(lower, upper)| ((angle - lower).to_radians(), (upper - angle).to_radians()));
+                prepare_limits(state, gaps, dt);
+            }
+            JointKind::Weld => {
+                state.point_mass = pin_mass(shape_a, point_a, shape_b, point_b);
+                state.point_bias = scale(sub(point_b, point_a), -POSITION_CORRECTION / dt);
+                state.lock_bias = -POSITION_CORRECTION / dt * angle.to_radians();
+            }
+            // Every impulse along or across the axis acts at B's anchor on both
+            // bodies, so A feels the lever of B sitting out along its axis
+            JointKind::Prismatic { axis, .. } | JointKind::Wheel { axis, .. } => {
+                let axis = world_axis(shapes, self.body_a, axis);
+                let across = (-axis.1, axis.0);
+                state.axis = axis;
+
+                let mut axial_mass = axial_inv_mass(shape_a, point_b, axis) + axial_inv_mass(shape_b, point_b, axis);
+                let across_mass = axial_inv_mass(shape_a, point_b, across) + axial_inv_mass(shape_b, point_b, across);
+                let drift = dot(sub(point_b, point_a), across);
+                state.softness = 0.0;
+
+                match self.kind {
+                    JointKind::Prismatic { limits, motor, .. } => {
+                        // Sliding across and turning are locked together, as
+                        // stopping one pushes on the other
+                        let lever_a = shape_a.map_or(0.0, |shape| cross(sub(point_b, centre(shape)), across));
+                        let lever_b = shape_b.map_or(0.0, |shape| cross(sub(point_b, centre(shape)), across));
+                        let coupling = inv_inertia(shape_a) * lever_a + inv_inertia(shape_b) * lever_b;
+                        // Neither body can turn, leaving only the sliding across to stop
+                        let turning = if angular_mass > 0.0 { angular_mass } else { 1.0 };
+
+                        state.point_mass = inverse([[across_mass, coupling], [coupling, turning]]).unwrap_or_default();
+                        state.point_bias = (
+                            -POSITION_CORRECTION / dt * drift,
+                            -POSITION_CORRECTION / dt * angle.to_radians(),
+                        );
+
+                        state.impulse = 0.0;
+                        prepare_motor(state, motor, dt);
+                        prepare_limits(state, limits.map(|(lower, upper)| (translation - lower, upper - translation)), dt);
+                    }
+                    JointKind::Wheel { stiffness, damping, motor, .. } => {
+                        let across_mass = if across_mass > 0.0 { 1.0 / across_mass } else { 0.0 };
+                        state.point_mass = [[across_mass, 0.0], [0.0, 0.0]];
+                        state.point_bias = (-POSITION_CORRECTION / dt * drift, 0.0);
+
+                        // The suspension rests with the anchors together
+                        state.bias = spring_bias(state, &mut axial_mass, translation, stiffness, damping, dt);
+                        prepare_motor(state, motor, dt);
+                    }
+                    _ => {}
+                }
+                state.axial_mass = if axial_mass > 0.0 { 1.0 / axial_mass } else { 0.0 };
+            }
+        }
+    }
+
+    pub(crate) fn warm_start(&self, shapes: &mut HashMap<Id, Shape>) {
+        let state = &self.state;
+        let limits_and_motor = state.motor_impulse + state.lower_impulse - state.upper_impulse;
+        let across = (-state.axis.1, state.axis.0);
+        match self.kind {
+            JointKind::Distance { .. } | JointKind::Rope { .. } | JointKind::Spring { .. } => {
+                self.apply(shapes, scale(state.axis, state.impulse));
+            }
+            JointKind::Revolute { .. } => {
+                self.apply(shapes, state.point_impulse);
+                self.apply_angular(shapes, limits_and_motor);
+            }
+            JointKind::Prismatic { .. } => {
+                self.apply_at_b(shapes, scale(across, state.point_impulse.0));
+                self.apply_angular(shapes, state.point_impulse.1);
+                self.apply_at_b(shapes, scale(state.axis, limits_and_motor));
+            }
+            JointKind::Wheel { .. } => {
+                self.apply_at_b(shapes, scale(across, state.point_impulse.0));
+                self.apply_at_b(shapes, scale(state.axis, state.impulse));
+                self.apply_angular(shapes, state.motor_impulse);
+            }
+            JointKind::Weld => {
+                self.apply(shapes, state.point_impulse);
+                self.apply_angular(shapes, state.lock_impulse);
+            }
+        }
+    }
+
+    pub(crate) fn solve(&mut self, shapes: &mut HashMap<Id, Shape>) {
+        let axis = self.state.axis;
+        let across = (-axis.1, axis.0);
+        match self.kind {
+            JointKind::Distance { .. } | JointKind::Rope { .. } | JointKind::Spring { .. } => {
+                let state = self.state;
+                let rel_vel = dot(self.relative_velocity(shapes), axis);
+                let lambda = -(rel_vel - state.bias + state.softness * state.impulse) * state.axial_mass;
+
+                let accumulated = match self.kind {
+                    // A rope can only pull
+                    JointKind::Rope { .. } => (state.impulse + lambda).min(0.0),
+                    _ => state.impulse + lambda,
+                };
+                self.state.impulse = accumulated;
+                self.apply(shapes, scale(axis, accumulated - state.impulse));
+            }
+            JointKind::Revolute { limits, motor } => {
+                // The motor first, so the limits and the pin can overrule it
+                let angular_mass = self.state.angular_mass;
+                if let Some(motor) = motor {
+                    let change = self.solve_motor(self.relative_spin(shapes), motor.speed.to_radians(), angular_mass);
+                    self.apply_angular(shapes, change);
+                }
+                if limits.is_some() {
+                    let change = self.solve_lower_limit(self.relative_spin(shapes), angular_mass);
+                    self.apply_angular(shapes, change);
+                    let change = self.solve_upper_limit(self.relative_spin(shapes), angular_mass);
+                    self.apply_angular(shapes, change);
+                }
+
+                let state = self.state;
+                let change = transform(state.point_mass, sub(state.point_bias, self.relative_velocity(shapes)));
+                self.state.point_impulse = (state.point_impulse.0 + change.0, state.point_impulse.1 + change.1);
+                self.apply(shapes, change);
+            }
+            JointKind::Prismatic { limits, motor, .. } => {
+                let axial_mass = self.state.axial_mass;
+                if let Some(motor) = motor {
+                    let change = self.solve_motor(self.sliding_velocity(shapes, axis), motor.speed, axial_mass);
+                    self.apply_at_b(shapes, scale(axis, change));
+                }
+                if limits.is_some() {
+                    let change = self.solve_lower_limit(self.sliding_velocity(shapes, axis), axial_mass);
+                    self.apply_at_b(shapes, scale(axis, change));
+                    let change = self.solve_upper_limit(self.sliding_velocity(shapes, axis), axial_mass);
+                    self.apply_at_b(shapes, scale(axis, change));
+                }
+
+                let state = self.state;
+                let velocity = (self.sliding_velocity(shapes, across), self.relative_spin(shapes));
+                let change = transform(state.point_mass, sub(state.point_bias, velocity));
+                self.state.point_impulse = (state.point_impulse.0 + change.0, state.point_impulse.1 + change.1);
+                self.apply_at_b(shapes, scale(across, change.0));
+                self.apply_angular(shapes, change.1);
+            }
+            JointKind::Wheel { motor, .. } => {
+                if let Some(motor) = motor {
+                    let change = self.solve_motor(self.relative_spin(shapes), motor.speed.to_radians(), self.state.angular_mass);
+                    self.apply_angular(shapes, change);
+                }
+
+                // Suspension, solved like a spring joint
+                let state = self.state;
+                let rel_vel = self.sliding_velocity(shapes, axis);
+                let lambda = -(rel_vel - state.bias + state.softness * state.impulse) * state.axial_mass;
+                self.state.impulse += lambda;
+                self.apply_at_b(shapes, scale(axis, lambda));
+
+                let state = self.state;
+                let lambda = (state.point_bias.0 - self.sliding_velocity(shapes, across)) * state.point_mass[0][0];
+                self.state.point_impulse.0 += lambda;
+                self.apply_at_b(shapes, scale(across, lambda));
+            }
+            JointKind::Weld => {
+                // The angle first, then the pin, like a revolute joint's motor
+                let state = self.state;
+                let lambda = -(self.relative_spin(shapes) - state.lock_bias) * state.angular_mass;
+                self.state.lock_impulse += lambda;
+                self.apply_angular(shapes, lambda);
+
+                let state = self.state;
+                let change = transform(state.point_mass, sub(state.point_bias, self.relative_velocity(shapes)));
+                self.state.point_impulse = (state.point_impulse.0 + change.0, state.point_impulse.1 + change.1);
+                self.apply(shapes, change);
+            }
+        }
+    }
+
+    // Whether the last substep pulled harder than the joint can take
+    pub(crate) fn breaks(&self, dt: f32) -> bool {
+        let (force, torque) = self.reaction(dt);
+        self.break_force.is_some_and(|limit| force > limit) || self.break_torque.is_some_and(|limit| torque > limit)
+    }
+
+    // Force and torque the joint held its bodies with over the last substep
+    fn reaction(&self, dt: f32) -> (f32, f32) {
+        let state = &self.state;
+        let limits_and_motor = state.motor_impulse + state.lower_impulse - state.upper_impulse;
+        let (impulse, angular_impulse) = match self.kind {
+            JointKind::Distance { .. } | JointKind::Rope { .. } | JointKind::Spring { .. } => (state.impulse.abs(), 0.0),
+            JointKind::Revolute { .. } => (length(state.point_impulse), limits_and_motor.abs()),
+            JointKind::Prismatic { .. } => (length((state.point_impulse.0, limits_and_motor)), state.point_impulse.1.abs()),
+            JointKind::Wheel { .. } => (length((state.point_impulse.0, state.impulse)), state.motor_impulse.abs()),
+            JointKind::Weld => (length(state.point_impulse), state.lock_impulse.abs()),
+        };
+        (impulse / dt, angular_impulse / dt)
+    }
+
+    // Pushes `velocity` towards the motor's speed, as far as the motor can.
+    // Returns the extra impulse to apply.
+    fn solve_motor(&mut self, velocity: f32, speed: f32, mass: f32) -> f32 {
+        let state = &mut self.state;
+        let lambda = -(velocity - speed) * mass;
+        let accumulated = (state.motor_impulse + lambda).clamp(-state.max_motor_impulse, state.max_motor_impulse);
+        let change = accumulated - state.motor_impulse;
+        state.motor_impulse = accumulated;
+        change
+    }
+
+    // Each limit can only push the joint back inside
+    fn solve_lower_limit(&mut self, velocity: f32, mass: f32) -> f32 {
+        let state = &mut self.state;
+        let lambda = -(velocity - state.lower_bias) * mass;
+        let accumulated = (state.lower_impulse + lambda).max(0.0);
+        let change = accumulated - state.lower_impulse;
+        state.lower_impulse = accumulated;
+        change
+    }
+
+    fn solve_upper_limit(&mut self, velocity: f32, mass: f32) -> f32 {
+        let state = &mut self.state;
+        let lambda = -(-velocity - state.upper_bias) * mass;
+        let accumulated = (state.upper_impulse + lambda).max(0.0);
+        let change = accumulated - state.upper_impulse;
+        state.upper_impulse = accumulated;
+        -change
+    }
+
+    // Velocity of B's anchor relative to A's
+    fn relative_velocity(&self, shapes: &HashMap<Id, Shape>) -> (f32, f32) {
+        sub(
+            body_velocity(shapes, self.body_b, self.state.point_b),
+            body_velocity(shapes, Some(self.body_a), self.state.point_a),
+        )
+    }
+
+    // Speed of B's anchor along `direction`, relative to the point of A under it
+    fn sliding_velocity(&self, shapes: &HashMap<Id, Shape>, direction: (f32, f32)) -> f32 {
+        let point = self.state.point_b;
+        dot(sub(body_velocity(shapes, self.body_b, point), body_velocity(shapes, Some(self.body_a), point)), direction)
+    }
+
+    // B's angular velocity relative to A's (radians/s)
+    fn relative_spin(&self, shapes: &HashMap<Id, Shape>) -> f32 {
+        (spin(shapes, self.body_b) - spin(shapes, Some(self.body_a))).to_radians()
+    }
+
+    // `impulse` pushes B's anchor, and the opposite pushes A's
+    fn apply(&self, shapes: &mut HashMap<Id, Shape>, impulse: (f32, f32)) {
+        if let Some(shape) = shapes.get_mut(&self.body_a) {
+            shape.apply_impulse((-impulse.0, -impulse.1), self.state.point_a);
+        }
+        if let Some(shape) = self.body_b.and_then(|id| shapes.get_mut(&id)) {
+            shape.apply_impulse(impulse, self.state.point_b);
+        }
+    }
+
+    // Like apply, but A takes its share at B's anchor too
+    fn apply_at_b(&self, shapes: &mut HashMap<Id, Shape>, impulse: (f32, f32)) {
+        if let Some(shape) = shapes.get_mut(&self.body_a) {
+            shape.apply_impulse((-impulse.0, -impulse.1), self.state.point_b);
+        }
+        if let Some(shape) = self.body_b.and_then(|id| shapes.get_mut(&id)) {
+            shape.apply_impulse(impulse, self.state.point_b);
+        }
+    }
+
+    // `impulse` turns B, and the opposite turns A
+    fn apply_angular(&self, shapes: &mut HashMap<Id, Shape>, impulse: f32) {
+        if let Some(shape) = shapes.get_mut(&self.body_a) {
+            shape.apply_angular_impulse(-impulse);
+        }
+        if let Some(shape) = self.body_b.and_then(|id| shapes.get_mut(&id)) {
+            shape.apply_angular_impulse(impulse);
+        }
+    }
+}
+
+// Inverse of the effective mass matrix for holding two points together
+fn pin_mass(shape_a: Option<&Shape>, point_a: (f32, f32), shape_b: Option<&Shape>, point_b: (f32, f32)) -> Mat2 {
+    let mut k = [[0.0; 2]; 2];
+    for (shape, point) in [(shape_a, point_a), (shape_b, point_b)] {
+        let Some(shape) = shape else { continue };
+        let r = sub(point, centre(shape));
+        let (inv_mass, inv_inertia) = (shape.inv_mass(), shape.inv_inertia());
+        k[0][0] += inv_mass + inv_inertia * r.1 * r.1;
+        k[0][1] -= inv_inertia * r.0 * r.1;
+        k[1][0] -= inv_inertia * r.0 * r.1;
+        k[1][1] += inv_mass + inv_inertia * r.0 * r.0;
+    }
+    inverse(k).unwrap_or_default()
+}
+
+// Springs are solved as a soft rod: each impulse lets it give a little, as much
+// as the spring would stretch under it over the substep. Adds the give to
+// `inv_mass` and returns the bias for a spring stretched by `stretch`.
+fn spring_bias(state: &mut JointState, inv_mass: &mut f32, stretch: f32, stiffness: f32, damping: f32, dt: f32) -> f32 {
+    let give = dt * (damping + dt * stiffness);
+    if give > 0.0 {
+        state.softness = 1.0 / give;
+        *inv_mass += state.softness;
+        -stretch * dt * stiffness * state.softness
+    } else {
+        // Neither stiff nor damped, it does nothing
+        *inv_mass = 0.0;
+        0.0
+    }
+}
+
+fn prepare_motor(state: &mut JointState, motor: Option<Motor>, dt: f32) {
+    match motor {
+        Some(motor) => state.max_motor_impulse = motor.max_torque * dt,
+        None => state.motor_impulse = 0.0,
+    }
+}
+
+// `gaps` are how far inside the lower and upper limit the joint is
+fn prepare_limits(state: &mut JointState, gaps: Option<(f32, f32)>, dt: f32) {
+    match gaps {
+        Some((lower_gap, upper_gap)) => {
+            state.lower_bias = limit_bias(lower_gap, dt);
+            state.upper_bias = limit_bias(upper_gap, dt);
+        }
+        None => {
+            state.lower_impulse = 0.0;
+            state.upper_impulse = 0.0;
+        }
+    }
+}
+
+// Speed a limit `gap` away lets the joint move towards it: enough to close the
+// gap within the substep while it's open, and backing out bit by bit once it's
+// been passed
+fn limit_bias(gap: f32, dt: f32) -> f32 {
+    if gap > 0.0 {
+        -gap / dt
+    } else {
+        -POSITION_CORRECTION / dt * gap
+    }
+}
+
+fn centre(shape: &Shape) -> (f32, f32) {
+    (shape.transform.x_pos, shape.transform.y_pos)
+}
+
+// A missing body is the world
+fn body(shapes: &HashMap<Id, Shape>, id: Option<Id>) -> Option<&Shape> {
+    id.and_then(|id| shapes.get(&id))
+}
+
+// The world's anchor is already in world space
+fn world_anchor(shapes: &HashMap<Id, Shape>, id: Option<Id>, anchor: (f32, f32)) -> (f32, f32) {
+    match body(shapes, id) {
+        Some(shape) => shape.world_point(anchor),
+        None => anchor,
+    }
+}
+
+// A slider's axis turns with A
+fn world_axis(shapes: &HashMap<Id, Shape>, id: Id, axis: (f32, f32)) -> (f32, f32) {
+    rotate(normalize(axis), rotation(shapes, Some(id)))
+}
+
+fn rotation(shapes: &HashMap<Id, Shape>, id: Option<Id>) -> f32 {
+    body(shapes, id).map_or(0.0, |shape| shape.transform.rotation)
+}
+
+fn spin(shapes: &HashMap<Id, Shape>, id: Option<Id>) -> f32 {
+    body(shapes, id).map_or(0.0, |shape| shape.transform.angular_vel)
+}
+
+fn inv_inertia(shape: Option<&Shape>) -> f32 {
+    shape.map_or(0.0, |shape| shape.inv_inertia())
+}
+
+fn body_velocity(shapes: &HashMap<Id, Shape>, id: Option<Id>, point: (f32, f32)) -> (f32, f32) {
+    body(shapes, id).map_or((0.0, 0.0), |shape| shape.velocity_at(point))
+}
+
+// Inverse mass a body shows to an impulse along `axis` at `point`
+fn axial_inv_mass(shape: Option<&Shape>, point: (f32, f32), axis: (f32, f32)) -> f32 {
+    shape.map_or(0.0, |shape| {
+        let r_cross_axis = cross(sub(point, centre(shape)), axis);
+        shape.inv_mass() + r_cross_axis * r_cross_axis * shape.inv_inertia()
+    })
+}
@@ -0,0 +1,23 @@
+//! A small 2D rigid body physics engine.
+//!
+//! The simulation lives in [`CollisionSpace`] (shapes, collision detection and
+//! resolution) and [`Space`] (the visual side: sprites and colours). Most users
+//! only need [`World`], which owns both and keeps them in sync.
+
+pub mod broadphase;
+pub mod collision;
+pub mod contacts;
+pub mod joints;
+pub mod controller;
+pub mod loader;
+mod math;
+mod solver;
+pub mod things;
+pub mod world;
+
+pub use collision::{CollisionSpace, Space};
+pub use things::{CollisionType, Dynamics, Shape, Sprite, Thing, Transform};
+pub use world::{BodyState, GravityMode, World};
+
+/// Identifier shared by a [`Thing`] and its [`Shape`] in the collision space.
+pub type Id = u16;
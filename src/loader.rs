@@ -3,12 +3,19 @@ use std::fs::File;
 use std::io::BufReader;
 use std::error::Error;
 
-use crate::things::*;
+use crate::things::{self, Dynamics, Transform};
+use crate::world::World;
+use crate::Id;
 
 #[derive(Debug, Serialize, Deserialize)]
 enum Sprite {
     Circle,
     Rectangle,
+    Polygon,
+    Capsule,
+    Compound,
+    Edge,
+    Chain,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,16 +30,132 @@ struct ThingData {
     r: u8,
     g: u8,
     b: u8,
+    #[serde(default)]
+    restitution: f32,
+    // Mass wins over density when both are given, without either the default density is used
+    #[serde(default)]
+    mass: Option<f32>,
+    #[serde(default)]
+    density: Option<f32>,
+    #[serde(default)]
+    static_friction: Option<f32>,
+    #[serde(default)]
+    dynamic_friction: Option<f32>,
+    // Swept collision against kinematic shapes, for small fast bodies
+    #[serde(default)]
+    ccd: bool,
+    // Polygon: convex outline, Chain: points along the line. As [x, y] pairs, relative to x_pos/y_pos
+    #[serde(default)]
+    vertices: Vec<(f32, f32)>,
+    // Compound only: the parts, placed relative to x_pos/y_pos and rotation
+    #[serde(default)]
+    children: Vec<ChildData>,
 }
 
-// fn load_things_from_file(path: &str) -> Result<Vec<Thing>, Box<dyn Error>> {
-//     let file = File::open(path)?;
-//     let reader = BufReader::new(file);
-//     let thing_data: Vec<ThingData> = serde_json::from_reader(reader)?;
-//
-//     for data in thing_data {
-//
-//     }
-//
-//     Ok(sprites)
-// }
+#[derive(Debug, Serialize, Deserialize)]
+struct ChildData {
+    sprite: Sprite,
+    #[serde(default)]
+    x_pos: f32,
+    #[serde(default)]
+    y_pos: f32,
+    width: u16,
+    height: u16,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default)]
+    vertices: Vec<(f32, f32)>,
+}
+
+fn simple_sprite(sprite: &Sprite, vertices: &[(f32, f32)]) -> Result<things::Sprite, Box<dyn Error>> {
+    let sprite = match sprite {
+        Sprite::Circle => things::Sprite::Circle,
+        Sprite::Rectangle => things::Sprite::Rectangle,
+        Sprite::Capsule => things::Sprite::Capsule,
+        Sprite::Polygon => things::Sprite::Polygon(vertices.to_vec()),
+        Sprite::Edge => things::Sprite::Edge,
+        Sprite::Chain => things::Sprite::Chain(vertices.to_vec()),
+        Sprite::Compound => return Err("Compound children can't be compound themselves".into()),
+    };
+
+    // Vertex counts and convexity, SAT gets concave polygons wrong
+    sprite.validate()?;
+    Ok(sprite)
+}
+
+impl ThingData {
+    fn sprite(&self) -> Result<things::Sprite, Box<dyn Error>> {
+        match self.sprite {
+            Sprite::Compound => {
+                let children = self.children.iter()
+                    .map(|child| Ok(things::ChildSprite {
+                        sprite: simple_sprite(&child.sprite, &child.vertices)?,
+                        offset: (child.x_pos, child.y_pos),
+                        rotation: child.rotation,
+                        width: child.width as i32,
+                        height: child.height as i32,
+                    }))
+                    .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+                let sprite = things::Sprite::Compound(children);
+                sprite.validate()?;
+                Ok(sprite)
+            }
+            _ => simple_sprite(&self.sprite, &self.vertices),
+        }
+    }
+}
+
+fn load_things_from_file(path: &str) -> Result<Vec<ThingData>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let thing_data: Vec<ThingData> = serde_json::from_reader(reader)?;
+
+    Ok(thing_data)
+}
+
+/// Registers every thing in a JSON scene file with the world.
+///
+/// Dynamic things become `Dynamics::Dynamic` shapes, the rest `Dynamics::Kinematic`.
+pub fn load_scene(path: &str, world: &mut World) -> Result<Vec<Id>, Box<dyn Error>> {
+    let mut ids = Vec::new();
+
+    for data in load_things_from_file(path)? {
+        let transform = Transform::new(
+            data.x_pos as f32,
+            data.y_pos as f32,
+            data.width as i32,
+            data.height as i32,
+            data.rotation as f32,
+        );
+        let dynamics = if data.dynamic { Dynamics::Dynamic } else { Dynamics::Kinematic };
+
+        let id = world.register(
+            transform,
+            data.sprite()?,
+            Some((data.r, data.g, data.b)),
+            Some(dynamics),
+            data.restitution,
+        )?;
+
+        if let Some(shape) = world.shape_mut(id) {
+            if let Some(density) = data.density {
+                shape.set_density(density);
+            }
+            if let Some(mass) = data.mass {
+                shape.set_mass(mass);
+            }
+            if let Some(static_friction) = data.static_friction {
+                shape.static_friction = static_friction;
+            }
+            if let Some(dynamic_friction) = data.dynamic_friction {
+                shape.dynamic_friction = dynamic_friction;
+            }
+            shape.ccd = data.ccd;
+        }
+
+        ids.push(id);
+    }
+
+    Ok(ids)
+}
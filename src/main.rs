@@ -1,32 +1,43 @@
-mod collision;
-mod loader;
-mod things;
-mod controller;
-
-use crate::collision::{CollisionSpace, Space};
+use physics_engine::controller::{find_pico_port, AccelerometerReader, Input, MAX_TILT_DEGREES};
+use physics_engine::joints::{Joint, JointEvent};
+use physics_engine::things::{self, *};
+use physics_engine::{GravityMode, World};
 use raylib::prelude::*;
-use things::*;
-use crate::controller::{find_pico_port, AccelerometerReader, Input};
-
-type Id = u16;
 
 const SCREEN_WIDTH: i32 = 920;
 const SCREEN_HEIGHT: i32 = 640;
 
 const DEBUG: bool = false;
 
+// Platform hinge motor: turns at HINGE_GAIN degrees/s per degree off the tilt
+const HINGE_GAIN: f32 = 8.0;
+const HINGE_MAX_SPEED: f32 = 180.0; // Degrees/s
+const HINGE_DEADBAND: f32 = 0.2; // Degrees
+const HINGE_TORQUE_PER_MASS: f32 = 1.0e6; // Enough to hold a full load of balls at either end
+
+// Springs under the bouncy pads: how fast a pad bobs (Hz), and how quickly that dies down
+const PAD_FREQUENCY: f32 = 8.0;
+const PAD_DAMPING_RATIO: f32 = 0.05;
+
+// Welds in the bucket on the platform each hold this many times the bucket
+// floor's weight before snapping, about two dozen balls' worth
+const BUCKET_STRENGTH: f32 = 10.0;
+const PLANK_THICKNESS: i32 = 10;
+
 
 fn main() {
     let (mut rl, thread) = init_visualiser();
 
     // Load visual data if available (textures, sprites)
 
-    // Create instance of empty collision space (check for collisions and update transform)
-    let mut collision_space = CollisionSpace::default();
-
-    // Create instance of space (list/tree of objects
+    // Create the world (collision space + list of things,
     // each holding visual ref. + shape (collide ref. + transform) data)
-    let mut space = Space::default();
+    let mut world = World::new();
+
+    // With --tilt-gravity the platform is held level and tilting the Pico turns gravity instead
+    if std::env::args().any(|arg| arg == "--tilt-gravity") {
+        world.set_gravity_mode(GravityMode::Tilt);
+    }
 
     // Register the platform
 
@@ -42,14 +53,113 @@ fn main() {
         p_height,
         p_rotation,
     );
-    let platform_id = space.register(
+    let platform_id = world.register(
         platform_transform,
         Sprite::Rectangle,
         Some((0,0,255)),
-        Some(Dynamics::Kinematic),
+        Some(Dynamics::Dynamic),
+        0.0,
+    ).unwrap();
+
+    // The platform is a lever on a post, turned by a motor in the hinge
+    let post_height = 60;
+    let post_transform = things::Transform::new(
+        p_x_pos,
+        p_y_pos + (post_height / 2) as f32,
+        20,
+        post_height,
         0.0,
-        &mut collision_space
     );
+    let post_id = world.register(post_transform, Sprite::Rectangle, Some((60, 60, 60)), Some(Dynamics::Kinematic), 0.0).unwrap();
+
+    let mut hinge = Joint::revolute(post_id, (0.0, -(post_height / 2) as f32), Some(platform_id), (0.0, 0.0));
+    hinge.set_limits(-MAX_TILT_DEGREES, MAX_TILT_DEGREES);
+    let platform_mass = world.shape(platform_id).unwrap().mass;
+    hinge.set_motor(0.0, platform_mass * HINGE_TORQUE_PER_MASS);
+    let hinge_id = world.add_joint(hinge).unwrap();
+
+    // Hang a short chain from each end of the platform
+    let chain_links = 5;
+    let link_length = 24.0;
+    for end in [-1.0, 1.0] {
+        let anchor = (end * (p_width / 2 - 10) as f32, 0.0);
+        let mut previous = (platform_id, anchor);
+
+        for i in 1..=chain_links {
+            let link_transform = things::Transform::new(
+                p_x_pos + anchor.0,
+                p_y_pos + link_length * i as f32,
+                12,
+                12,
+                0.0,
+            );
+            let link_id = world.register(link_transform, Sprite::Circle, Some((0, 0, 120)), Some(Dynamics::Dynamic), 0.2).unwrap();
+
+            let (body, body_anchor) = previous;
+            world.add_joint(Joint::distance(body, body_anchor, Some(link_id), (0.0, 0.0), link_length));
+            previous = (link_id, (0.0, 0.0));
+        }
+    }
+
+    // Bouncy pads on springs in the bottom corners, catching what misses the platform
+    let pad_y_pos = (SCREEN_HEIGHT - 60) as f32;
+    for pad_x_pos in [70.0, (SCREEN_WIDTH - 70) as f32] {
+        let pad_transform = things::Transform::new(pad_x_pos, pad_y_pos, 110, 12, 0.0);
+        let pad_id = world.register(pad_transform, Sprite::Rectangle, Some((200, 120, 0)), Some(Dynamics::Dynamic), 1.0).unwrap();
+
+        // Each end has a spring straight down and one crossing under the pad,
+        // without the crossed ones the pad would sway over and fold up
+        let pad_mass = world.shape(pad_id).unwrap().mass;
+        let omega = 2.0 * std::f32::consts::PI * PAD_FREQUENCY;
+        let stiffness = pad_mass * omega * omega / 4.0;
+        let damping = 2.0 * PAD_DAMPING_RATIO * pad_mass * omega / 4.0;
+        for side in [-1.0, 1.0] {
+            let pad_anchor = (side * 45.0, 0.0);
+            for ground in [(pad_x_pos + side * 45.0, SCREEN_HEIGHT as f32), (pad_x_pos - side * 45.0, SCREEN_HEIGHT as f32)] {
+                let rest_length = ((ground.0 - pad_x_pos - pad_anchor.0).powi(2) + (ground.1 - pad_y_pos).powi(2)).sqrt();
+                world.add_joint(Joint::spring(pad_id, pad_anchor, None, ground, rest_length, stiffness, damping));
+            }
+        }
+    }
+
+    // A bucket welded together on stilts on the platform, which breaks apart
+    // once enough balls pile in
+    let leg_height = 50.0;
+    let floor_width = 220.0;
+    let wall_height = 80.0;
+    let half_thickness = (PLANK_THICKNESS / 2) as f32;
+    let platform_top = p_y_pos - (p_height / 2) as f32;
+    let floor_y_pos = platform_top - leg_height - half_thickness;
+    let floor_transform = things::Transform::new(p_x_pos, floor_y_pos, floor_width as i32, PLANK_THICKNESS, 0.0);
+    let floor_id = world.register(floor_transform, Sprite::Rectangle, Some((140, 90, 40)), Some(Dynamics::Dynamic), 0.0).unwrap();
+
+    let break_force = BUCKET_STRENGTH * world.shape(floor_id).unwrap().mass * world.gravity().1;
+    for side in [-1.0, 1.0] {
+        let x_offset = side * (floor_width / 2.0 - half_thickness);
+        let leg_transform = things::Transform::new(p_x_pos + x_offset, platform_top - leg_height / 2.0, PLANK_THICKNESS, leg_height as i32, 0.0);
+        let leg_id = world.register(leg_transform, Sprite::Rectangle, Some((140, 90, 40)), Some(Dynamics::Dynamic), 0.0).unwrap();
+        // Standing on the floor's end, just outside it
+        let wall_transform = things::Transform::new(
+            p_x_pos + x_offset + side * PLANK_THICKNESS as f32,
+            floor_y_pos + half_thickness - wall_height / 2.0,
+            PLANK_THICKNESS,
+            wall_height as i32,
+            0.0,
+        );
+        let wall_id = world.register(wall_transform, Sprite::Rectangle, Some((140, 90, 40)), Some(Dynamics::Dynamic), 0.0).unwrap();
+
+        let welds = [
+            Joint::weld(platform_id, (x_offset, -(p_height / 2) as f32), Some(leg_id), (0.0, leg_height / 2.0), 0.0),
+            Joint::weld(leg_id, (0.0, -leg_height / 2.0), Some(floor_id), (x_offset, half_thickness), 0.0),
+            Joint::weld(floor_id, (side * floor_width / 2.0, 0.0), Some(wall_id), (-side * half_thickness, wall_height / 2.0 - half_thickness), 0.0),
+        ];
+        for mut weld in welds {
+            // A joint holds about as much torque as a plank is thick
+            weld.break_force = Some(break_force);
+            weld.break_torque = Some(break_force * PLANK_THICKNESS as f32);
+            world.add_joint(weld);
+        }
+    }
 
     let port_name = find_pico_port()
         .ok_or("Could not find Pico").unwrap();
@@ -67,15 +177,16 @@ fn main() {
     let diameter = 20;
 
     let mut frame_count = 0;
+    let mut broken_joints = 0;
 
     while !rl.window_should_close() {
 
         frame_count += 1;
 
-        if space.things.len() < num_objects as usize && frame_count % 20 == 0 {
+        if world.len() < num_objects as usize && frame_count % 20 == 0 {
             for i in 0..num_to_add {
                 let ball_transform = things::Transform::new((SCREEN_WIDTH / num_to_add * i) as f32, 30.0, diameter, diameter, 0.0);
-                space.register(ball_transform, Sprite::Circle, None, Some(Dynamics::Dynamic), 0.6, &mut collision_space);
+                world.register(ball_transform, Sprite::Circle, None, Some(Dynamics::Dynamic), 0.6).unwrap();
             }
         }
 
@@ -84,23 +195,49 @@ fn main() {
             platform_axes = new_input;
         }
 
-        // Handle platform (kinematic) updates
-        let platform : &mut Shape = &mut collision_space.shapes.get_mut(&platform_id).unwrap();
-        platform.set_rotation(((platform_axes.x * 100.0).round() / 100.0) * 65.0);
+        world.apply_input(&platform_axes);
+
+        // Drive the hinge motor towards the tilt, or keep the platform level when
+        // tilting turns gravity instead
+        let target = match world.gravity_mode() {
+            GravityMode::Fixed => platform_axes.tilt_degrees(),
+            GravityMode::Tilt => 0.0,
+        };
+        // The post is body A and never turns, so the hinge angle is the platform's
+        // rotation and a positive motor speed turns it towards a positive tilt
+        let error = target - world.shape(platform_id).unwrap().transform.rotation;
+        // Close enough counts as there, so the motor stops changing and the pile can sleep
+        let speed = if error.abs() < HINGE_DEADBAND {
+            0.0
+        } else {
+            (error * HINGE_GAIN).clamp(-HINGE_MAX_SPEED, HINGE_MAX_SPEED)
+        };
+        world.set_motor_speed(hinge_id, speed);
 
         // Handle collision / transform updates
-        collision_space.update();
-
+        world.step(rl.get_frame_time());
+        for event in world.joint_events() {
+            let JointEvent::Broken { id, .. } = event;
+            if DEBUG {
+                println!("Joint {} broke", id);
+            }
+            broken_joints += 1;
+        }
 
         // Handle visualisation
         let mut d = rl.begin_drawing(&thread);
         d.clear_background(Color::WHITE);
 
 
+        // Scenery without a shape, behind everything that moves
+        for (t, transform) in world.visuals() {
+            let (r, g, b) = t.color;
+            draw_sprite(&mut d, &t.sprite, transform, Color::new(r, g, b, 255));
+        }
+
         // For each object, draw and handle off-screen behaviour
-        for (id, t) in &space.things {
-            // println!("Drawing thing {}", id);
-            let shape = collision_space.shapes.get_mut(&id).unwrap();
+        for (t, shape) in world.bodies_mut() {
+            // println!("Drawing thing {}", t.id);
             let transform: &mut things::Transform = &mut shape.transform;
             let (r, g, b) = t.color;
 
@@ -108,35 +245,12 @@ fn main() {
             if DEBUG && shape.colliding {
                 color = Color::RED;
             }
-
-            match t.sprite {
-                Sprite::Circle => {
-                    d.draw_circle(
-                        transform.x_pos as i32,
-                        transform.y_pos as i32,
-                        (transform.height / 2) as f32,
-                        color
-                    )
-                },
-                Sprite::Rectangle => {
-
-                    d.draw_rectangle_pro(
-                        Rectangle::new(
-                            transform.x_pos,
-                            transform.y_pos,
-                            transform.width as f32,
-                            transform.height as f32
-                        ),
-                        Vector2::new(
-                            (transform.width / 2) as f32,
-                            (transform.height / 2) as f32
-                        ),
-                        transform.rotation,
-                        color
-                    );
-                }
+            if DEBUG && shape.sleeping {
+                color = Color::GRAY;
             }
 
+            draw_sprite(&mut d, &t.sprite, transform, color);
+
             // Handle off-screen
             if transform.x_pos > (SCREEN_WIDTH + transform.width) as f32 {
                 transform.x_pos =  0.0 - transform.width as f32
@@ -155,12 +269,125 @@ fn main() {
             }
         }
 
+        for (joint_id, _) in world.joints() {
+            let (a, b) = world.joint_anchors(joint_id).unwrap();
+            d.draw_line_ex(Vector2::new(a.0, a.1), Vector2::new(b.0, b.1), 2.0, Color::DARKGRAY);
+        }
+
         // Draw debug information
-        let debug_text = format!("FPS: {}\nNumber of Objects: {}", d.get_fps(), space.things.len());
+        let debug_text = format!("FPS: {}\nNumber of Objects: {}\nBroken Joints: {}", d.get_fps(), world.len(), broken_joints);
         d.draw_text(&debug_text, 10, 10, 5, Color::BLACK);
     }
 }
 
+fn draw_sprite(d: &mut impl RaylibDraw, sprite: &Sprite, transform: &things::Transform, color: Color) {
+    match sprite {
+        Sprite::Circle => {
+            d.draw_circle(
+                transform.x_pos as i32,
+                transform.y_pos as i32,
+                (transform.height / 2) as f32,
+                color
+            )
+        },
+        Sprite::Rectangle => {
+
+            d.draw_rectangle_pro(
+                Rectangle::new(
+                    transform.x_pos,
+                    transform.y_pos,
+                    transform.width as f32,
+                    transform.height as f32
+                ),
+                Vector2::new(
+                    (transform.width / 2) as f32,
+                    (transform.height / 2) as f32
+                ),
+                transform.rotation,
+                color
+            );
+        },
+        Sprite::Polygon(vertices) => {
+            let (sin, cos) = transform.rotation.to_radians().sin_cos();
+
+            // Stored counter-clockwise with +Y up, Raylib wants counter-clockwise on screen
+            let points: Vec<Vector2> = vertices.iter().rev()
+                .map(|&(x, y)| Vector2::new(
+                    transform.x_pos + x * cos - y * sin,
+                    transform.y_pos + x * sin + y * cos,
+                ))
+                .collect();
+
+            d.draw_triangle_fan(&points, color);
+        },
+        Sprite::Capsule => {
+            // Box between the two end circles
+            let radius = transform.height as f32 / 2.0;
+            let half_length = (transform.width as f32 / 2.0 - radius).max(0.0);
+            let (sin, cos) = transform.rotation.to_radians().sin_cos();
+
+            d.draw_rectangle_pro(
+                Rectangle::new(
+                    transform.x_pos,
+                    transform.y_pos,
+                    half_length * 2.0,
+                    transform.height as f32
+                ),
+                Vector2::new(half_length, radius),
+                transform.rotation,
+                color
+            );
+            d.draw_circle_v(
+                Vector2::new(transform.x_pos - half_length * cos, transform.y_pos - half_length * sin),
+                radius,
+                color
+            );
+            d.draw_circle_v(
+                Vector2::new(transform.x_pos + half_length * cos, transform.y_pos + half_length * sin),
+                radius,
+                color
+            );
+        },
+        Sprite::Compound(children) => {
+            let (sin, cos) = transform.rotation.to_radians().sin_cos();
+
+            for child in children {
+                let (x, y) = child.offset;
+                let child_transform = things::Transform::new(
+                    transform.x_pos + x * cos - y * sin,
+                    transform.y_pos + x * sin + y * cos,
+                    child.width,
+                    child.height,
+                    transform.rotation + child.rotation,
+                );
+
+                draw_sprite(d, &child.sprite, &child_transform, color);
+            }
+        },
+        Sprite::Edge | Sprite::Chain(_) => {
+            let (sin, cos) = transform.rotation.to_radians().sin_cos();
+            let half_width = transform.width as f32 / 2.0;
+
+            let points: Vec<Vector2> = match sprite {
+                Sprite::Chain(points) => points.iter()
+                    .map(|&(x, y)| Vector2::new(
+                        transform.x_pos + x * cos - y * sin,
+                        transform.y_pos + x * sin + y * cos,
+                    ))
+                    .collect(),
+                _ => vec![
+                    Vector2::new(transform.x_pos - half_width * cos, transform.y_pos - half_width * sin),
+                    Vector2::new(transform.x_pos + half_width * cos, transform.y_pos + half_width * sin),
+                ],
+            };
+
+            for segment in points.windows(2) {
+                d.draw_line_ex(segment[0], segment[1], 3.0, color);
+            }
+        }
+    }
+}
+
 fn init_visualiser() -> (RaylibHandle, RaylibThread) {
     let (mut rl, thread) = raylib::init()
         .size(SCREEN_WIDTH, SCREEN_HEIGHT)
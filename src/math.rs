@@ -0,0 +1,165 @@
+// Small helpers for 2D vectors stored as (x, y) tuples
+
+pub(crate) type Vec2 = (f32, f32);
+pub(crate) type Mat2 = [[f32; 2]; 2]; // Rows
+
+pub(crate) fn add(a: Vec2, b: Vec2) -> Vec2 {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+pub(crate) fn sub(a: Vec2, b: Vec2) -> Vec2 {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+pub(crate) fn scale(a: Vec2, factor: f32) -> Vec2 {
+    (a.0 * factor, a.1 * factor)
+}
+
+pub(crate) fn dot(a: Vec2, b: Vec2) -> f32 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+// Z component of the 3D cross product
+pub(crate) fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+pub(crate) fn length(a: Vec2) -> f32 {
+    dot(a, a).sqrt()
+}
+
+pub(crate) fn normalize(a: Vec2) -> Vec2 {
+    let len = length(a);
+    if len > 0.0 { (a.0 / len, a.1 / len) } else { (0.0, 0.0) }
+}
+
+pub(crate) fn negate(a: Vec2) -> Vec2 {
+    (-a.0, -a.1)
+}
+
+// Rotates by `rotation_deg` degrees, same direction as Transform::rotation
+pub(crate) fn rotate(a: Vec2, rotation_deg: f32) -> Vec2 {
+    let (sin, cos) = rotation_deg.to_radians().sin_cos();
+    (a.0 * cos - a.1 * sin, a.0 * sin + a.1 * cos)
+}
+
+// Local point of a body at `position` with `rotation_deg` into world space
+pub(crate) fn to_world(local: Vec2, position: Vec2, rotation_deg: f32) -> Vec2 {
+    add(rotate(local, rotation_deg), position)
+}
+
+pub(crate) fn transform(m: Mat2, a: Vec2) -> Vec2 {
+    (m[0][0] * a.0 + m[0][1] * a.1, m[1][0] * a.0 + m[1][1] * a.1)
+}
+
+// None for a singular matrix
+pub(crate) fn inverse(m: Mat2) -> Option<Mat2> {
+    let determinant = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    if determinant == 0.0 {
+        return None;
+    }
+
+    Some([
+        [m[1][1] / determinant, -m[0][1] / determinant],
+        [-m[1][0] / determinant, m[0][0] / determinant],
+    ])
+}
+
+// Signed area, positive for counter-clockwise vertices (with +Y up)
+pub(crate) fn polygon_area(vertices: &[Vec2]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..vertices.len() {
+        area += cross(vertices[i], vertices[(i + 1) % vertices.len()]);
+    }
+
+    area / 2.0
+}
+
+pub(crate) fn polygon_centroid(vertices: &[Vec2]) -> Vec2 {
+    let area = polygon_area(vertices);
+    if area == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let mut centroid = (0.0, 0.0);
+    for i in 0..vertices.len() {
+        let v1 = vertices[i];
+        let v2 = vertices[(i + 1) % vertices.len()];
+        centroid = add(centroid, scale(add(v1, v2), cross(v1, v2)));
+    }
+
+    scale(centroid, 1.0 / (6.0 * area))
+}
+
+// Moment of inertia of a uniform polygon around the origin
+pub(crate) fn polygon_inertia(vertices: &[Vec2], mass: f32) -> f32 {
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+
+    for i in 0..vertices.len() {
+        let v1 = vertices[i];
+        let v2 = vertices[(i + 1) % vertices.len()];
+        let weight = cross(v1, v2).abs();
+        numerator += weight * (dot(v1, v1) + dot(v1, v2) + dot(v2, v2));
+        denominator += weight;
+    }
+
+    if denominator == 0.0 { 0.0 } else { mass * numerator / (6.0 * denominator) }
+}
+
+pub(crate) fn closest_point_on_segment(point: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let ab = sub(b, a);
+    let length_sq = dot(ab, ab);
+    if length_sq == 0.0 {
+        return a;
+    }
+
+    let t = (dot(sub(point, a), ab) / length_sq).clamp(0.0, 1.0);
+    add(a, scale(ab, t))
+}
+
+// Closest points between segments p1-q1 and p2-q2 (one on each)
+pub(crate) fn closest_points_segments(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> (Vec2, Vec2) {
+    let d1 = sub(q1, p1);
+    let d2 = sub(q2, p2);
+    let r = sub(p1, p2);
+    let a = dot(d1, d1);
+    let e = dot(d2, d2);
+    let f = dot(d2, r);
+
+    if a == 0.0 && e == 0.0 {
+        return (p1, p2);
+    }
+
+    let (s, t) = if a == 0.0 {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = dot(d1, r);
+        if e == 0.0 {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = dot(d1, d2);
+            let denominator = a * e - b * b;
+
+            // Parallel segments give a zero denominator, any s works then
+            let mut s = if denominator != 0.0 {
+                ((b * f - c * e) / denominator).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let mut t = (b * s + f) / e;
+
+            if t < 0.0 {
+                t = 0.0;
+                s = (-c / a).clamp(0.0, 1.0);
+            } else if t > 1.0 {
+                t = 1.0;
+                s = ((b - c) / a).clamp(0.0, 1.0);
+            }
+
+            (s, t)
+        }
+    };
+
+    (add(p1, scale(d1, s)), add(p2, scale(d2, t)))
+}
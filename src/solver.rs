@@ -0,0 +1,346 @@
+// Contact solver: sequential impulses over contact manifolds.
+// Each pass nudges every contact's accumulated impulse towards the value that
+// stops the bodies moving into each other (and sliding, within the friction
+// limit). A few passes over all contacts settle piles and stacks that a single
+// pair-at-a-time push can't. Impulses carry over to the next solve through the
+// contact cache as a starting point (warm starting), so resting contacts start
+// out nearly solved.
+
+use std::collections::HashMap;
+
+use crate::contacts::{Contact, ContactCache, ContactKey};
+use crate::joints::Joint;
+use crate::math::{add, cross, dot, scale, sub, Mat2};
+use crate::things::{Dynamics, Shape};
+use crate::Id;
+
+pub const DEFAULT_ITERATIONS: u32 = 8;
+
+// Overlap (px) left alone, so resting contacts stay touching from one substep to the next
+const PENETRATION_SLOP: f32 = 0.5;
+// Share of the remaining overlap pushed out per substep (Baumgarte factor)
+pub(crate) const POSITION_CORRECTION: f32 = 0.2;
+// Slower impacts than this (px/s) don't bounce, so resting bodies settle instead of buzzing
+const RESTITUTION_THRESHOLD: f32 = 120.0;
+// Two-point manifolds closer to singular than this are solved one point at a time
+const MAX_CONDITION_NUMBER: f32 = 1000.0;
+
+// Up to two contact points between a pair of bodies, sharing one normal
+pub(crate) struct Manifold {
+    id_a: Id,
+    id_b: Id,
+    normal: (f32, f32), // From A towards B
+    tangent: (f32, f32),
+    restitution: f32,
+    static_friction: f32,
+    dynamic_friction: f32,
+    points: [ContactPoint; 2],
+    point_count: usize,
+    // Two-point manifolds solve both normal impulses together, so a box resting
+    // on a face doesn't rock from one corner to the other. K couples the points.
+    block: Option<(Mat2, Mat2)>, // K and its inverse
+}
+
+#[derive(Default, Copy, Clone)]
+struct ContactPoint {
+    position: (f32, f32), // World space
+    feature: u32, // What made it, see `ContactKey`
+    penetration: f32,
+    r_a: (f32, f32), // From each centre to the contact
+    r_b: (f32, f32),
+    normal_mass: f32, // Effective mass along the normal and the tangent
+    tangent_mass: f32,
+    velocity_bias: f32, // Separating speed to aim for: bounce or overlap correction
+    normal_impulse: f32, // Accumulated over the iterations
+    tangent_impulse: f32,
+}
+
+impl Manifold {
+    pub(crate) fn new(
+        id_a: Id,
+        id_b: Id,
+        normal: (f32, f32),
+        contacts: &[(f32, f32)],
+        depths: &[f32],
+        features: &[u32],
+        shapes: &HashMap<Id, Shape>,
+    ) -> Self {
+        let shape_a = &shapes[&id_a];
+        let shape_b = &shapes[&id_b];
+
+        // Bouncing off something that doesn't move uses the moving body's restitution, damped a bit
+        let restitution = match (shape_a.dynamics, shape_b.dynamics) {
+            (Dynamics::Dynamic, Dynamics::Dynamic) => shape_a.restitution * shape_b.restitution,
+            (Dynamics::Dynamic, _) => shape_a.restitution * 0.8,
+            _ => shape_b.restitution * 0.8,
+        };
+
+        let mut points = [ContactPoint::default(); 2];
+        for (i, point) in points.iter_mut().enumerate().take(contacts.len()) {
+            point.position = contacts[i];
+            point.penetration = depths[i];
+            point.feature = features[i];
+        }
+
+        Manifold {
+            id_a,
+            id_b,
+            normal,
+            tangent: (-normal.1, normal.0),
+            restitution,
+            static_friction: (shape_a.static_friction * shape_b.static_friction).sqrt(),
+            dynamic_friction: (shape_a.dynamic_friction * shape_b.dynamic_friction).sqrt(),
+            points,
+            point_count: contacts.len().min(2),
+            block: None,
+        }
+    }
+
+    fn points_mut(&mut self) -> &mut [ContactPoint] {
+        &mut self.points[..self.point_count]
+    }
+
+    fn key(&self, point: &ContactPoint) -> ContactKey {
+        ContactKey { id_a: self.id_a, id_b: self.id_b, feature: point.feature }
+    }
+}
+
+pub(crate) struct ContactSolver {
+    pub iterations: u32,
+}
+
+impl Default for ContactSolver {
+    fn default() -> Self {
+        ContactSolver {
+            iterations: DEFAULT_ITERATIONS,
+        }
+    }
+}
+
+impl ContactSolver {
+    // Changes the velocities of the shapes in `manifolds` so they stop pushing
+    // into each other, and of the ones held by `joints` so they keep to them.
+    // Positions are left to the integrator; overlap and joint drift are worked
+    // off a bit at a time through the velocity bias.
+    pub(crate) fn solve(
+        &self,
+        shapes: &mut HashMap<Id, Shape>,
+        contacts: &mut ContactCache,
+        mut manifolds: Vec<Manifold>,
+        joints: &mut [&mut Joint],
+        dt: f32,
+    ) {
+        // Bounces are worked out from the velocities before any impulse is applied
+        for manifold in &mut manifolds {
+            prepare(shapes, contacts, manifold, dt);
+        }
+        for joint in joints.iter_mut() {
+            joint.prepare(shapes, dt);
+        }
+        for manifold in &manifolds {
+            warm_start(shapes, manifold);
+        }
+        for joint in joints.iter() {
+            joint.warm_start(shapes);
+        }
+
+        // Joints first, so contacts have the last word on bodies not overlapping
+        for _ in 0..self.iterations {
+            for joint in joints.iter_mut() {
+                joint.solve(shapes);
+            }
+            for manifold in &mut manifolds {
+                solve_manifold(shapes, manifold);
+            }
+        }
+
+        let solved = manifolds.iter()
+            .flat_map(|manifold| manifold.points[..manifold.point_count].iter().map(move |point| {
+                (manifold.key(point), Contact {
+                    position: point.position,
+                    normal: manifold.normal,
+                    depth: point.penetration,
+                    normal_impulse: point.normal_impulse,
+                    tangent_impulse: point.tangent_impulse,
+                    age: 0,
+                })
+            }))
+            .collect();
+        contacts.update(solved, shapes);
+    }
+}
+
+fn prepare(shapes: &HashMap<Id, Shape>, contacts: &ContactCache, manifold: &mut Manifold, dt: f32) {
+    let shape_a = &shapes[&manifold.id_a];
+    let shape_b = &shapes[&manifold.id_b];
+    let inv_mass_sum = shape_a.inv_mass() + shape_b.inv_mass();
+    let (inv_inertia_a, inv_inertia_b) = (shape_a.inv_inertia(), shape_b.inv_inertia());
+    let (id_a, id_b) = (manifold.id_a, manifold.id_b);
+    let (normal, tangent, restitution) = (manifold.normal, manifold.tangent, manifold.restitution);
+
+    for point in manifold.points_mut() {
+        point.r_a = sub(point.position, (shape_a.transform.x_pos, shape_a.transform.y_pos));
+        point.r_b = sub(point.position, (shape_b.transform.x_pos, shape_b.transform.y_pos));
+
+        let r_a_cross_n = cross(point.r_a, normal);
+        let r_b_cross_n = cross(point.r_b, normal);
+        let normal_mass = inv_mass_sum
+            + r_a_cross_n * r_a_cross_n * inv_inertia_a
+            + r_b_cross_n * r_b_cross_n * inv_inertia_b;
+        point.normal_mass = if normal_mass > 0.0 { 1.0 / normal_mass } else { 0.0 };
+
+        let r_a_cross_t = cross(point.r_a, tangent);
+        let r_b_cross_t = cross(point.r_b, tangent);
+        let tangent_mass = inv_mass_sum
+            + r_a_cross_t * r_a_cross_t * inv_inertia_a
+            + r_b_cross_t * r_b_cross_t * inv_inertia_b;
+        point.tangent_mass = if tangent_mass > 0.0 { 1.0 / tangent_mass } else { 0.0 };
+
+        // Bounce off the approach speed, or at least push out the overlap beyond the slop
+        let rel_vel = sub(shape_b.velocity_at(point.position), shape_a.velocity_at(point.position));
+        let vel_along_normal = dot(rel_vel, normal);
+        let bounce = if -vel_along_normal > RESTITUTION_THRESHOLD {
+            -restitution * vel_along_normal
+        } else {
+            0.0
+        };
+        let correction = POSITION_CORRECTION / dt * (point.penetration - PENETRATION_SLOP).max(0.0);
+        point.velocity_bias = bounce.max(correction);
+
+        // Pick up where the same contact left off last solve
+        if let Some(contact) = contacts.get(&ContactKey { id_a, id_b, feature: point.feature }) {
+            point.normal_impulse = contact.normal_impulse;
+            point.tangent_impulse = contact.tangent_impulse;
+        }
+    }
+
+    manifold.block = None;
+    if let [point_1, point_2] = &manifold.points[..manifold.point_count] {
+        let r_1_a = cross(point_1.r_a, normal);
+        let r_1_b = cross(point_1.r_b, normal);
+        let r_2_a = cross(point_2.r_a, normal);
+        let r_2_b = cross(point_2.r_b, normal);
+
+        let k_11 = inv_mass_sum + r_1_a * r_1_a * inv_inertia_a + r_1_b * r_1_b * inv_inertia_b;
+        let k_22 = inv_mass_sum + r_2_a * r_2_a * inv_inertia_a + r_2_b * r_2_b * inv_inertia_b;
+        let k_12 = inv_mass_sum + r_1_a * r_2_a * inv_inertia_a + r_1_b * r_2_b * inv_inertia_b;
+        let determinant = k_11 * k_22 - k_12 * k_12;
+
+        if determinant > 0.0 && k_11 * k_11 < MAX_CONDITION_NUMBER * determinant {
+            let inverse = [
+                [k_22 / determinant, -k_12 / determinant],
+                [-k_12 / determinant, k_11 / determinant],
+            ];
+            manifold.block = Some(([[k_11, k_12], [k_12, k_22]], inverse));
+        }
+    }
+}
+
+fn warm_start(shapes: &mut HashMap<Id, Shape>, manifold: &Manifold) {
+    for point in &manifold.points[..manifold.point_count] {
+        let impulse = add(scale(manifold.normal, point.normal_impulse), scale(manifold.tangent, point.tangent_impulse));
+        apply_pair_impulse(shapes, manifold.id_a, manifold.id_b, impulse, point.position);
+    }
+}
+
+fn solve_manifold(shapes: &mut HashMap<Id, Shape>, manifold: &mut Manifold) {
+    let (id_a, id_b) = (manifold.id_a, manifold.id_b);
+    let (normal, tangent) = (manifold.normal, manifold.tangent);
+    let (static_friction, dynamic_friction) = (manifold.static_friction, manifold.dynamic_friction);
+
+    // Friction first, bounded by the normal impulse. Below the static limit
+    // the sliding stops dead, above it the dynamic coefficient applies.
+    for point in manifold.points_mut() {
+        let rel_vel = relative_velocity(shapes, id_a, id_b, point.position);
+        let lambda = -dot(rel_vel, tangent) * point.tangent_mass;
+
+        let wanted = point.tangent_impulse + lambda;
+        let accumulated = if wanted.abs() <= point.normal_impulse * static_friction {
+            wanted
+        } else {
+            let limit = point.normal_impulse * dynamic_friction;
+            wanted.clamp(-limit, limit)
+        };
+        let change = accumulated - point.tangent_impulse;
+        point.tangent_impulse = accumulated;
+
+        apply_pair_impulse(shapes, id_a, id_b, scale(tangent, change), point.position);
+    }
+
+    if let Some((k, inverse)) = manifold.block {
+        solve_block(shapes, manifold, k, inverse);
+        return;
+    }
+
+    // The accumulated normal impulse may only push the bodies apart
+    for point in manifold.points_mut() {
+        let rel_vel = relative_velocity(shapes, id_a, id_b, point.position);
+        let lambda = -(dot(rel_vel, normal) - point.velocity_bias) * point.normal_mass;
+
+        let accumulated = (point.normal_impulse + lambda).max(0.0);
+        let change = accumulated - point.normal_impulse;
+        point.normal_impulse = accumulated;
+
+        apply_pair_impulse(shapes, id_a, id_b, scale(normal, change), point.position);
+    }
+}
+
+// Both normal impulses of a two-point manifold at once. Finds the accumulated
+// impulses x >= 0 with the separating speeds K x + b >= 0 where x > 0 is 0,
+// trying both points pushing, then either one, then neither.
+fn solve_block(shapes: &mut HashMap<Id, Shape>, manifold: &mut Manifold, k: Mat2, inverse: Mat2) {
+    let (id_a, id_b, normal) = (manifold.id_a, manifold.id_b, manifold.normal);
+    let [point_1, point_2] = &mut manifold.points;
+
+    let old = [point_1.normal_impulse, point_2.normal_impulse];
+    let vel_1 = dot(relative_velocity(shapes, id_a, id_b, point_1.position), normal);
+    let vel_2 = dot(relative_velocity(shapes, id_a, id_b, point_2.position), normal);
+
+    // Speeds there would be with no accumulated impulse at all
+    let b = [
+        vel_1 - point_1.velocity_bias - (k[0][0] * old[0] + k[0][1] * old[1]),
+        vel_2 - point_2.velocity_bias - (k[1][0] * old[0] + k[1][1] * old[1]),
+    ];
+
+    let both = [
+        -(inverse[0][0] * b[0] + inverse[0][1] * b[1]),
+        -(inverse[1][0] * b[0] + inverse[1][1] * b[1]),
+    ];
+    let only_1 = -b[0] / k[0][0];
+    let only_2 = -b[1] / k[1][1];
+
+    let new = if both[0] >= 0.0 && both[1] >= 0.0 {
+        both
+    } else if only_1 >= 0.0 && k[1][0] * only_1 + b[1] >= 0.0 {
+        [only_1, 0.0]
+    } else if only_2 >= 0.0 && k[0][1] * only_2 + b[0] >= 0.0 {
+        [0.0, only_2]
+    } else if b[0] >= 0.0 && b[1] >= 0.0 {
+        [0.0, 0.0]
+    } else {
+        // No exact answer (shouldn't happen), leave the impulses as they are
+        old
+    };
+
+    point_1.normal_impulse = new[0];
+    point_2.normal_impulse = new[1];
+    let (position_1, position_2) = (point_1.position, point_2.position);
+
+    apply_pair_impulse(shapes, id_a, id_b, scale(normal, new[0] - old[0]), position_1);
+    apply_pair_impulse(shapes, id_a, id_b, scale(normal, new[1] - old[1]), position_2);
+}
+
+// Velocity of B's contact point relative to A's
+fn relative_velocity(shapes: &HashMap<Id, Shape>, id_a: Id, id_b: Id, point: (f32, f32)) -> (f32, f32) {
+    sub(shapes[&id_b].velocity_at(point), shapes[&id_a].velocity_at(point))
+}
+
+// `impulse` pushes B, and the opposite pushes A
+fn apply_pair_impulse(shapes: &mut HashMap<Id, Shape>, id_a: Id, id_b: Id, impulse: (f32, f32), point: (f32, f32)) {
+    if let Some(shape_a) = shapes.get_mut(&id_a) {
+        shape_a.apply_impulse((-impulse.0, -impulse.1), point);
+    }
+    if let Some(shape_b) = shapes.get_mut(&id_b) {
+        shape_b.apply_impulse(impulse, point);
+    }
+}
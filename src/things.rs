@@ -1,5 +1,15 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::math::{add, cross, dot, length, polygon_area, polygon_centroid, polygon_inertia, rotate, scale, sub, to_world};
 use crate::Id;
 
+pub const DEFAULT_DENSITY: f32 = 1.0; // Mass per px^2
+pub const DEFAULT_STATIC_FRICTION: f32 = 0.5;
+pub const DEFAULT_DYNAMIC_FRICTION: f32 = 0.3;
+// Shorter polygon edges or chain segments (px) have no direction to collide along
+const MIN_EDGE_LENGTH: f32 = 0.01;
+
 pub struct Transform {
     pub x_pos: f32,
     pub y_pos: f32,
@@ -10,10 +20,12 @@ pub struct Transform {
     pub vel_y: f32,
     pub accel_x: f32,
     pub accel_y: f32,
+    pub angular_vel: f32, // Degrees per second, like rotation
+    pub angular_accel: f32, // Degrees per second^2
 }
 
 impl Transform {
-    pub(crate) fn new(x_pos: f32, y_pos: f32, width: i32, height: i32, rotation: f32) -> Self {
+    pub fn new(x_pos: f32, y_pos: f32, width: i32, height: i32, rotation: f32) -> Self {
         Transform {
             x_pos,
             y_pos,
@@ -23,7 +35,9 @@ impl Transform {
             vel_x: 0.0,
             vel_y: 0.0,
             accel_x: 0.0,
-            accel_y: 0.0
+            accel_y: 0.0,
+            angular_vel: 0.0,
+            angular_accel: 0.0,
         }
     }
 }
@@ -31,6 +45,63 @@ impl Transform {
 pub enum CollisionType {
     Circle,
     Rectangle,
+    // Convex, vertices relative to the transform position (which should be their centroid)
+    Polygon(Vec<(f32, f32)>),
+    // Segment along the local x axis with rounded ends: width is the full length, height the diameter
+    Capsule,
+    // Several shapes moving as one body, placed around the transform position (the centre of mass)
+    Compound(Vec<ChildShape>),
+    // Line segment along the local x axis, width is the length. Static only
+    Edge,
+    // Connected line segments through local space points, repeat the first point to close it.
+    // Shapes slide across the joints between segments smoothly. Static only
+    Chain(Vec<(f32, f32)>),
+}
+
+// One part of a compound shape
+pub struct ChildShape {
+    pub collision_type: CollisionType,
+    pub offset: (f32, f32), // From the parent position, in the parent's local space
+    pub rotation: f32, // Relative to the parent
+    pub width: i32,
+    pub height: i32,
+}
+
+impl ChildShape {
+    // Where the child is in the world, given where its parent is
+    pub(crate) fn world_transform(&self, parent: &Transform) -> Transform {
+        let (x_pos, y_pos) = to_world(self.offset, (parent.x_pos, parent.y_pos), parent.rotation);
+        Transform::new(x_pos, y_pos, self.width, self.height, parent.rotation + self.rotation)
+    }
+}
+
+// World space ends of a capsule's core segment, and its radius
+pub(crate) fn capsule_segment(transform: &Transform) -> ((f32, f32), (f32, f32), f32) {
+    let radius = transform.height as f32 / 2.0;
+    let half_length = (transform.width as f32 / 2.0 - radius).max(0.0);
+    let axis = rotate((half_length, 0.0), transform.rotation);
+
+    (
+        (transform.x_pos - axis.0, transform.y_pos - axis.1),
+        (transform.x_pos + axis.0, transform.y_pos + axis.1),
+        radius,
+    )
+}
+
+// World space points of an edge or chain, empty for other shapes
+pub(crate) fn chain_points(collision_type: &CollisionType, transform: &Transform) -> Vec<(f32, f32)> {
+    let position = (transform.x_pos, transform.y_pos);
+
+    match collision_type {
+        CollisionType::Edge => {
+            let axis = rotate((transform.width as f32 / 2.0, 0.0), transform.rotation);
+            vec![sub(position, axis), add(position, axis)]
+        }
+        CollisionType::Chain(points) => points.iter()
+            .map(|&point| to_world(point, position, transform.rotation))
+            .collect(),
+        _ => Vec::new(),
+    }
 }
 
 impl From<&Sprite> for CollisionType {
@@ -38,7 +109,205 @@ impl From<&Sprite> for CollisionType {
         match value {
             Sprite::Circle => CollisionType::Circle,
             Sprite::Rectangle => CollisionType::Rectangle,
+            Sprite::Polygon(vertices) => CollisionType::Polygon(vertices.clone()),
+            Sprite::Capsule => CollisionType::Capsule,
+            Sprite::Compound(children) => CollisionType::Compound(
+                children.iter()
+                    .map(|child| ChildShape {
+                        collision_type: CollisionType::from(&child.sprite),
+                        offset: child.offset,
+                        rotation: child.rotation,
+                        width: child.width,
+                        height: child.height,
+                    })
+                    .collect()
+            ),
+            Sprite::Edge => CollisionType::Edge,
+            Sprite::Chain(points) => CollisionType::Chain(points.clone()),
+        }
+    }
+}
+
+// Collision code expects counter-clockwise polygons
+fn fix_winding(collision_type: &mut CollisionType) {
+    match collision_type {
+        CollisionType::Polygon(vertices) if polygon_area(vertices) < 0.0 => vertices.reverse(),
+        CollisionType::Compound(children) => {
+            for child in children {
+                fix_winding(&mut child.collision_type);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Axis-aligned bounding box (min_x, min_y, max_x, max_y) of a shape placed at `transform`
+pub(crate) fn collision_aabb(collision_type: &CollisionType, transform: &Transform) -> (f32, f32, f32, f32) {
+    let half_w = transform.width as f32 / 2.0;
+    let half_h = transform.height as f32 / 2.0;
+
+    let (extent_x, extent_y) = match collision_type {
+        CollisionType::Circle => (half_w, half_w),
+        CollisionType::Rectangle => {
+            let (sin, cos) = transform.rotation.to_radians().sin_cos();
+            (
+                half_w * cos.abs() + half_h * sin.abs(),
+                half_w * sin.abs() + half_h * cos.abs(),
+            )
+        }
+        CollisionType::Polygon(vertices) => {
+            let mut min = (f32::MAX, f32::MAX);
+            let mut max = (f32::MIN, f32::MIN);
+            for &vertex in vertices {
+                let (x, y) = rotate(vertex, transform.rotation);
+                min = (min.0.min(x), min.1.min(y));
+                max = (max.0.max(x), max.1.max(y));
+            }
+
+            return (
+                transform.x_pos + min.0,
+                transform.y_pos + min.1,
+                transform.x_pos + max.0,
+                transform.y_pos + max.1,
+            );
+        }
+        CollisionType::Capsule => {
+            let (start, _, radius) = capsule_segment(transform);
+            (
+                (transform.x_pos - start.0).abs() + radius,
+                (transform.y_pos - start.1).abs() + radius,
+            )
+        }
+        CollisionType::Compound(children) => {
+            let mut bounds = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+            for child in children {
+                let (min_x, min_y, max_x, max_y) = collision_aabb(&child.collision_type, &child.world_transform(transform));
+                bounds = (bounds.0.min(min_x), bounds.1.min(min_y), bounds.2.max(max_x), bounds.3.max(max_y));
+            }
+
+            return bounds;
+        }
+        CollisionType::Edge | CollisionType::Chain(_) => {
+            let mut bounds = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+            for (x, y) in chain_points(collision_type, transform) {
+                bounds = (bounds.0.min(x), bounds.1.min(y), bounds.2.max(x), bounds.3.max(y));
+            }
+
+            return bounds;
+        }
+    };
+
+    (
+        transform.x_pos - extent_x,
+        transform.y_pos - extent_y,
+        transform.x_pos + extent_x,
+        transform.y_pos + extent_y,
+    )
+}
+
+fn collision_area(collision_type: &CollisionType, width: i32, height: i32) -> f32 {
+    let width = width as f32;
+    let height = height as f32;
+
+    match collision_type {
+        CollisionType::Circle => std::f32::consts::PI * (width / 2.0) * (width / 2.0),
+        CollisionType::Rectangle => width * height,
+        CollisionType::Polygon(vertices) => polygon_area(vertices).abs(),
+        CollisionType::Capsule => {
+            let radius = height / 2.0;
+            let length = (width - height).max(0.0);
+            std::f32::consts::PI * radius * radius + length * height
+        }
+        CollisionType::Compound(children) => children.iter()
+            .map(|child| collision_area(&child.collision_type, child.width, child.height))
+            .sum(),
+        CollisionType::Edge | CollisionType::Chain(_) => 0.0,
+    }
+}
+
+// Moment of inertia around the shape's position
+fn collision_inertia(collision_type: &CollisionType, width: i32, height: i32, mass: f32) -> f32 {
+    let width = width as f32;
+    let height = height as f32;
+
+    match collision_type {
+        CollisionType::Circle => 0.5 * mass * (width / 2.0) * (width / 2.0),
+        CollisionType::Rectangle => mass * (width * width + height * height) / 12.0,
+        CollisionType::Polygon(vertices) => polygon_inertia(vertices, mass),
+        CollisionType::Capsule => {
+            // Box in the middle plus two half circles, each around the centre
+            let radius = height / 2.0;
+            let length = (width - height).max(0.0);
+            let circle_area = std::f32::consts::PI * radius * radius;
+            let box_area = length * height;
+            let circle_mass = mass * circle_area / (circle_area + box_area);
+            let box_mass = mass - circle_mass;
+
+            let half_length = length / 2.0;
+            let centroid_offset = 4.0 * radius / (3.0 * std::f32::consts::PI);
+            circle_mass * (0.5 * radius * radius + half_length * half_length + 2.0 * half_length * centroid_offset)
+                + box_mass * (height * height + length * length) / 12.0
+        }
+        CollisionType::Compound(children) => {
+            let total_area = collision_area(collision_type, 0, 0);
+            if total_area == 0.0 {
+                return 0.0;
+            }
+
+            // Each child around its own position, moved out to the parent's (parallel axis theorem)
+            children.iter()
+                .map(|child| {
+                    let child_mass = mass * collision_area(&child.collision_type, child.width, child.height) / total_area;
+                    collision_inertia(&child.collision_type, child.width, child.height, child_mass)
+                        + child_mass * dot(child.offset, child.offset)
+                })
+                .sum()
+        }
+        CollisionType::Edge | CollisionType::Chain(_) => 0.0,
+    }
+}
+
+// Moves a sprite's outline so its centroid sits on the local origin.
+// Returns where the centroid was, so the caller can move the position onto it.
+pub(crate) fn centre_sprite(sprite: &mut Sprite) -> (f32, f32) {
+    match sprite {
+        Sprite::Polygon(vertices) => {
+            if polygon_area(vertices) < 0.0 {
+                vertices.reverse();
+            }
+
+            let centroid = polygon_centroid(vertices);
+            for vertex in vertices.iter_mut() {
+                *vertex = sub(*vertex, centroid);
+            }
+
+            centroid
+        }
+        Sprite::Compound(children) => {
+            // Centre each child first, then weigh the children by area
+            let mut weighted_sum = (0.0, 0.0);
+            let mut total_area = 0.0;
+            for child in children.iter_mut() {
+                let child_centroid = centre_sprite(&mut child.sprite);
+                child.offset = add(child.offset, rotate(child_centroid, child.rotation));
+
+                let area = collision_area(&CollisionType::from(&child.sprite), child.width, child.height);
+                weighted_sum = add(weighted_sum, scale(child.offset, area));
+                total_area += area;
+            }
+
+            if total_area == 0.0 {
+                return (0.0, 0.0);
+            }
+
+            let centroid = scale(weighted_sum, 1.0 / total_area);
+            for child in children.iter_mut() {
+                child.offset = sub(child.offset, centroid);
+            }
+
+            centroid
         }
+        _ => (0.0, 0.0),
     }
 }
 
@@ -55,22 +324,261 @@ pub struct Shape {
     pub dynamics: Dynamics,
     pub restitution: f32,
     pub colliding: bool,
+    pub density: f32,
+    pub mass: f32,
+    pub inertia: f32, // Moment of inertia around the centre
+    pub static_friction: f32, // Grip while at rest relative to the other surface
+    pub dynamic_friction: f32, // Drag while sliding
+    pub sleeping: bool, // At rest, skipped by integration and narrowphase until woken
+    pub sleep_time: f32, // How long (seconds) it has been moving slowly enough to sleep
+    pub ccd: bool, // Swept against kinematic shapes so it can't pass through them between substeps
+    pub target_pose: Option<(f32, f32, f32)>, // Kinematic only: where to be by the end of the next step (x, y, rotation)
 }
 
 impl Shape {
+    pub fn new(transform: Transform, mut collision_type: CollisionType, mut dynamics: Dynamics, restitution: f32) -> Self {
+        fix_winding(&mut collision_type);
+
+        // Edges and chains have no area to give them mass, so they never move on their own
+        if matches!(collision_type, CollisionType::Edge | CollisionType::Chain(_))
+            && matches!(dynamics, Dynamics::Dynamic)
+        {
+            dynamics = Dynamics::Kinematic;
+        }
+
+        let mut shape = Shape {
+            transform,
+            collision_type,
+            dynamics,
+            restitution,
+            colliding: false,
+            density: DEFAULT_DENSITY,
+            mass: 0.0,
+            inertia: 0.0,
+            static_friction: DEFAULT_STATIC_FRICTION,
+            dynamic_friction: DEFAULT_DYNAMIC_FRICTION,
+            sleeping: false,
+            sleep_time: 0.0,
+            ccd: false,
+            target_pose: None,
+        };
+        shape.set_density(DEFAULT_DENSITY);
+
+        shape
+    }
+
+    // Axis-aligned bounding box (min_x, min_y, max_x, max_y), rotation included
+    pub fn aabb(&self) -> (f32, f32, f32, f32) {
+        collision_aabb(&self.collision_type, &self.transform)
+    }
+
+    pub fn area(&self) -> f32 {
+        collision_area(&self.collision_type, self.transform.width, self.transform.height)
+    }
+
+    // Sets the mass directly, the density follows from the area
+    pub fn set_mass(&mut self, mass: f32) {
+        self.mass = mass;
+        let area = self.area();
+        self.density = if area > 0.0 { mass / area } else { 0.0 };
+        self.update_inertia();
+    }
+
+    // Sets the density, the mass follows from the area
+    pub fn set_density(&mut self, density: f32) {
+        self.density = density;
+        self.mass = density * self.area();
+        self.update_inertia();
+    }
+
+    pub fn set_friction(&mut self, static_friction: f32, dynamic_friction: f32) {
+        self.static_friction = static_friction;
+        self.dynamic_friction = dynamic_friction;
+    }
+
+    fn update_inertia(&mut self) {
+        self.inertia = collision_inertia(&self.collision_type, self.transform.width, self.transform.height, self.mass);
+    }
+
+    // Static and kinematic shapes have infinite mass, so nothing can push them
+    pub fn inv_mass(&self) -> f32 {
+        if matches!(self.dynamics, Dynamics::Dynamic) && self.mass > 0.0 {
+            1.0 / self.mass
+        } else {
+            0.0
+        }
+    }
+
+    pub fn inv_inertia(&self) -> f32 {
+        if matches!(self.dynamics, Dynamics::Dynamic) && self.inertia > 0.0 {
+            1.0 / self.inertia
+        } else {
+            0.0
+        }
+    }
+
+    // Velocity of the point of the shape at `point` (world space), spin included
+    pub fn velocity_at(&self, point: (f32, f32)) -> (f32, f32) {
+        let angular_vel = self.transform.angular_vel.to_radians();
+        let r_x = point.0 - self.transform.x_pos;
+        let r_y = point.1 - self.transform.y_pos;
+
+        (
+            self.transform.vel_x - angular_vel * r_y,
+            self.transform.vel_y + angular_vel * r_x,
+        )
+    }
+
+    // Applies an impulse at `point` (world space). Off-centre impulses also spin the shape.
+    pub fn apply_impulse(&mut self, impulse: (f32, f32), point: (f32, f32)) {
+        let inv_mass = self.inv_mass();
+        self.transform.vel_x += impulse.0 * inv_mass;
+        self.transform.vel_y += impulse.1 * inv_mass;
+
+        let r_x = point.0 - self.transform.x_pos;
+        let r_y = point.1 - self.transform.y_pos;
+        let angular_impulse = r_x * impulse.1 - r_y * impulse.0;
+        self.transform.angular_vel += (angular_impulse * self.inv_inertia()).to_degrees();
+    }
+
+    // Spins the shape without pushing it anywhere
+    pub fn apply_angular_impulse(&mut self, impulse: f32) {
+        self.transform.angular_vel += (impulse * self.inv_inertia()).to_degrees();
+    }
+
+    // Teleports the shape, its surface doesn't move as far as contacts can tell.
+    // Kinematic shapes that should carry things along use set_target_pose.
     pub fn set_rotation(&mut self, rotation: f32) {
         self.transform.rotation = rotation;
     }
 
+    // A world space point in the shape's own frame, e.g. for a joint anchor
+    pub fn local_point(&self, point: (f32, f32)) -> (f32, f32) {
+        let offset = (point.0 - self.transform.x_pos, point.1 - self.transform.y_pos);
+        rotate(offset, -self.transform.rotation)
+    }
+
+    pub fn world_point(&self, local: (f32, f32)) -> (f32, f32) {
+        to_world(local, (self.transform.x_pos, self.transform.y_pos), self.transform.rotation)
+    }
+
+    // Linear (px/s) and angular (degrees/s) velocity. Kinematic shapes keep
+    // moving at this speed until it's changed, pushing whatever is in the way.
+    pub fn set_velocity(&mut self, vel_x: f32, vel_y: f32, angular_vel: f32) {
+        self.transform.vel_x = vel_x;
+        self.transform.vel_y = vel_y;
+        self.transform.angular_vel = angular_vel;
+    }
+
+    // Moves a kinematic shape to this pose over the next step, with the velocity
+    // that gets it there, then stops it. Things it touches on the way are pushed
+    // and flung by its surface instead of just being shoved out of the overlap.
+    pub fn set_target_pose(&mut self, x_pos: f32, y_pos: f32, rotation: f32) {
+        self.target_pose = Some((x_pos, y_pos, rotation));
+    }
+
     pub fn set_colliding(&mut self, colliding: bool) {
         self.colliding = colliding;
     }
+
+    // Only this shape, CollisionSpace::wake also wakes the shapes resting on it
+    pub fn wake(&mut self) {
+        self.sleeping = false;
+        self.sleep_time = 0.0;
+    }
+
+    pub(crate) fn fall_asleep(&mut self) {
+        self.sleeping = true;
+        self.transform.vel_x = 0.0;
+        self.transform.vel_y = 0.0;
+        self.transform.angular_vel = 0.0;
+    }
 }
 
 #[derive(Debug)]
 pub enum Sprite {
     Circle,
     Rectangle,
+    Polygon(Vec<(f32, f32)>), // Local space vertices
+    Capsule,
+    Compound(Vec<ChildSprite>),
+    Edge,
+    Chain(Vec<(f32, f32)>), // Local space points
+}
+
+impl Sprite {
+    // Collision needs polygons to be convex with at least 3 corners, chains to
+    // have at least one segment, neither with repeated points, and compounds at
+    // least one part
+    pub fn validate(&self) -> Result<(), InvalidSprite> {
+        match self {
+            Sprite::Polygon(vertices) if vertices.len() < 3 => Err(InvalidSprite::TooFewVertices),
+            Sprite::Polygon(vertices) if has_short_edge(vertices.iter().chain(vertices.first())) => Err(InvalidSprite::ShortEdge),
+            Sprite::Polygon(vertices) if !is_convex(vertices) => Err(InvalidSprite::NotConvex),
+            Sprite::Chain(points) if points.len() < 2 => Err(InvalidSprite::TooFewVertices),
+            Sprite::Chain(points) if has_short_edge(points.iter()) => Err(InvalidSprite::ShortEdge),
+            Sprite::Compound(children) if children.is_empty() => Err(InvalidSprite::EmptyCompound),
+            Sprite::Compound(children) => children.iter().try_for_each(|child| child.sprite.validate()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InvalidSprite {
+    TooFewVertices,
+    NotConvex, // Or all the corners on one line
+    ShortEdge, // Two points in a row (nearly) on top of each other
+    EmptyCompound,
+}
+
+impl fmt::Display for InvalidSprite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidSprite::TooFewVertices => write!(f, "Polygon needs at least 3 vertices, chain at least 2"),
+            InvalidSprite::NotConvex => write!(f, "Polygon must be convex"),
+            InvalidSprite::ShortEdge => write!(f, "Polygon and chain points can't repeat"),
+            InvalidSprite::EmptyCompound => write!(f, "Compound needs at least 1 child"),
+        }
+    }
+}
+
+impl Error for InvalidSprite {}
+
+// Whether any two points in a row are closer than MIN_EDGE_LENGTH
+fn has_short_edge<'a>(points: impl Iterator<Item = &'a (f32, f32)> + Clone) -> bool {
+    points.clone().zip(points.skip(1)).any(|(&a, &b)| length(sub(b, a)) < MIN_EDGE_LENGTH)
+}
+
+// Every corner turns the same way, in either winding, and all the way round
+// only once (a star turns the same way at every corner too)
+fn is_convex(vertices: &[(f32, f32)]) -> bool {
+    let mut turning = 0.0;
+    let mut total_angle: f32 = 0.0;
+    for i in 0..vertices.len() {
+        let a = vertices[i];
+        let b = vertices[(i + 1) % vertices.len()];
+        let c = vertices[(i + 2) % vertices.len()];
+        let turn = cross(sub(b, a), sub(c, b));
+        if turn * turning < 0.0 {
+            return false;
+        }
+        if turn != 0.0 {
+            turning = turn;
+        }
+        total_angle += turn.atan2(dot(sub(b, a), sub(c, b)));
+    }
+
+    turning != 0.0 && total_angle.abs() < 3.0 * std::f32::consts::PI
+}
+
+#[derive(Debug)]
+pub struct ChildSprite {
+    pub sprite: Sprite,
+    pub offset: (f32, f32), // From the parent position, in the parent's local space
+    pub rotation: f32, // Relative to the parent
+    pub width: i32,
+    pub height: i32,
 }
 
 
@@ -82,4 +590,64 @@ pub struct Thing {
     pub sprite: Sprite,
     pub color: (u8, u8, u8),
     pub dynamic: bool,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convex_polygons_are_valid_either_way_round() {
+        let triangle = vec![(0.0, -25.0), (25.0, 20.0), (-25.0, 20.0)];
+        let reversed = triangle.iter().rev().copied().collect();
+        assert_eq!(Sprite::Polygon(triangle).validate(), Ok(()));
+        assert_eq!(Sprite::Polygon(reversed).validate(), Ok(()));
+    }
+
+    #[test]
+    fn polygons_need_three_vertices() {
+        assert_eq!(Sprite::Polygon(vec![]).validate(), Err(InvalidSprite::TooFewVertices));
+        assert_eq!(Sprite::Polygon(vec![(0.0, 0.0), (10.0, 0.0)]).validate(), Err(InvalidSprite::TooFewVertices));
+    }
+
+    #[test]
+    fn concave_and_flat_polygons_are_rejected() {
+        let arrow = vec![(0.0, 0.0), (20.0, 10.0), (0.0, 20.0), (5.0, 10.0)];
+        assert_eq!(Sprite::Polygon(arrow).validate(), Err(InvalidSprite::NotConvex));
+
+        let star = (0..5)
+            .map(|i| {
+                let angle = (i as f32 * 144.0).to_radians();
+                (20.0 * angle.cos(), 20.0 * angle.sin())
+            })
+            .collect();
+        assert_eq!(Sprite::Polygon(star).validate(), Err(InvalidSprite::NotConvex));
+
+        let line = vec![(0.0, 0.0), (10.0, 0.0), (20.0, 0.0)];
+        assert_eq!(Sprite::Polygon(line).validate(), Err(InvalidSprite::NotConvex));
+    }
+
+    #[test]
+    fn repeated_points_are_rejected() {
+        let repeated = vec![(0.0, 0.0), (0.0, 0.0), (40.0, 0.0), (0.0, 40.0)];
+        assert_eq!(Sprite::Polygon(repeated).validate(), Err(InvalidSprite::ShortEdge));
+
+        // Closing the outline by hand repeats the first point too
+        let closed = vec![(0.0, 0.0), (40.0, 0.0), (0.0, 40.0), (0.0, 0.0)];
+        assert_eq!(Sprite::Polygon(closed).validate(), Err(InvalidSprite::ShortEdge));
+
+        let chain = vec![(0.0, 0.0), (50.0, 0.0), (50.0, 0.001), (100.0, 0.0)];
+        assert_eq!(Sprite::Chain(chain).validate(), Err(InvalidSprite::ShortEdge));
+    }
+
+    #[test]
+    fn compounds_need_a_valid_child() {
+        assert_eq!(Sprite::Compound(vec![]).validate(), Err(InvalidSprite::EmptyCompound));
+
+        let child = |sprite| ChildSprite { sprite, offset: (0.0, 0.0), rotation: 0.0, width: 10, height: 10 };
+        assert_eq!(Sprite::Compound(vec![child(Sprite::Rectangle)]).validate(), Ok(()));
+        assert_eq!(
+            Sprite::Compound(vec![child(Sprite::Rectangle), child(Sprite::Polygon(vec![]))]).validate(),
+            Err(InvalidSprite::TooFewVertices),
+        );
+    }
+}
@@ -0,0 +1,785 @@
+use serde::Serialize;
+
+use crate::broadphase::Broadphase;
+use crate::collision::{CollisionSpace, Space};
+use crate::contacts::ContactCache;
+use crate::controller::Input;
+use crate::joints::{Joint, JointEvent, JointId};
+use crate::things::{Dynamics, InvalidSprite, Shape, Sprite, Thing, Transform};
+use crate::Id;
+
+/// A simulation world: the collision space plus the things living in it.
+///
+/// Register things with [`World::register`], advance the simulation with
+/// [`World::step`] and read back positions through [`World::shape`] or
+/// [`World::bodies`].
+#[derive(Default)]
+pub struct World {
+    collision_space: CollisionSpace,
+    space: Space,
+    gravity_mode: GravityMode,
+}
+
+/// Where the world's gravity comes from.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum GravityMode {
+    /// Gravity only changes through [`World::set_gravity`].
+    #[default]
+    Fixed,
+    /// [`World::apply_input`] points gravity the way the controller is tilted,
+    /// like marbles in a box.
+    Tilt,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a thing to the world and returns its id.
+    ///
+    /// Things with `Dynamics::Dynamic` or `Dynamics::Kinematic` also get a shape
+    /// in the collision space; anything else, including no dynamics at all, is
+    /// visual only: it stays where `transform` puts it, see [`World::visuals`].
+    /// Without a color the thing is drawn black.
+    ///
+    /// Fails if the sprite can't collide: a polygon with fewer than 3 vertices
+    /// or that isn't convex, a chain with fewer than 2 points, a polygon or chain
+    /// with the same point twice in a row, or a compound with no children (see
+    /// [`Sprite::validate`]).
+    pub fn register(&mut self,
+                    transform: Transform,
+                    sprite: Sprite,
+                    color: Option<(u8, u8, u8)>,
+                    dynamics: Option<Dynamics>,
+                    restitution: f32,
+    ) -> Result<Id, InvalidSprite> {
+        self.space.register(transform, sprite, color, dynamics, restitution, &mut self.collision_space)
+    }
+
+    /// Advances the simulation by `dt` seconds.
+    ///
+    /// Physics runs in fixed steps of [`FIXED_TIMESTEP`](crate::collision::FIXED_TIMESTEP);
+    /// time left over is carried into the next call, so the simulation speed does
+    /// not depend on how often this is called. A `dt` that is NaN, infinite, zero
+    /// or negative is ignored.
+    pub fn step(&mut self, dt: f32) {
+        self.collision_space.update(dt);
+    }
+
+    /// Gravity vector in px/s², +Y pointing down the screen.
+    pub fn gravity(&self) -> (f32, f32) {
+        self.collision_space.gravity()
+    }
+
+    /// Sets the gravity vector in px/s².
+    ///
+    /// Sleeping bodies only wake once gravity has turned more than about a
+    /// degree or changed size by more than about 1% since it last woke them,
+    /// so a noisy controller in [`GravityMode::Tilt`] doesn't keep them awake.
+    pub fn set_gravity(&mut self, gravity_x: f32, gravity_y: f32) {
+        self.collision_space.set_gravity(gravity_x, gravity_y);
+    }
+
+    /// Picks how the world finds pairs of shapes that might be touching.
+    ///
+    /// The default [`UniformGrid`](crate::broadphase::UniformGrid) suits many
+    /// similar-sized shapes. See [`broadphase`](crate::broadphase) for the others.
+    pub fn set_broadphase(&mut self, broadphase: Box<dyn Broadphase>) {
+        self.collision_space.set_broadphase(broadphase);
+    }
+
+    /// Every contact point from the last step, keyed by body pair and feature.
+    ///
+    /// Each [`Contact`](crate::contacts::Contact) carries the solver's impulses and
+    /// its age, and [`Contact::status`](crate::contacts::Contact::status) tells a
+    /// contact that just started from one that was already there.
+    pub fn contacts(&self) -> &ContactCache {
+        self.collision_space.contacts()
+    }
+
+    pub fn solver_iterations(&self) -> u32 {
+        self.collision_space.solver_iterations()
+    }
+
+    /// Sets how many passes the contact solver makes over all contacts each
+    /// substep (8 by default). Tall stacks and big piles settle better with more.
+    pub fn set_solver_iterations(&mut self, iterations: u32) {
+        self.collision_space.set_solver_iterations(iterations);
+    }
+
+    pub fn gravity_mode(&self) -> GravityMode {
+        self.gravity_mode
+    }
+
+    pub fn set_gravity_mode(&mut self, gravity_mode: GravityMode) {
+        self.gravity_mode = gravity_mode;
+    }
+
+    /// Feeds a controller reading to the world.
+    ///
+    /// In [`GravityMode::Tilt`] this turns gravity to match the tilt, keeping its
+    /// magnitude. In [`GravityMode::Fixed`] it does nothing.
+    pub fn apply_input(&mut self, input: &Input) {
+        if self.gravity_mode == GravityMode::Tilt {
+            self.collision_space.set_gravity_from_tilt(input);
+        }
+    }
+
+    /// Wakes a sleeping body and every body in its island.
+    ///
+    /// Contacts and moving kinematic bodies wake bodies on their own; this is
+    /// for changes the simulation can't see, like moving a shape by hand.
+    pub fn wake(&mut self, id: Id) {
+        self.collision_space.wake(id);
+    }
+
+    /// Moves a kinematic body to this pose (px, degrees) over the next step.
+    ///
+    /// Unlike setting its transform directly, the body gets the velocity that
+    /// takes it there, so things resting on it are carried along and pushed
+    /// aside instead of shoved out of the overlap. It stops once it arrives.
+    /// Does nothing for bodies without a shape.
+    pub fn set_target_pose(&mut self, id: Id, x_pos: f32, y_pos: f32, rotation: f32) {
+        if let Some(shape) = self.collision_space.shapes.get_mut(&id) {
+            shape.set_target_pose(x_pos, y_pos, rotation);
+        }
+    }
+
+    /// Adds a joint and returns its id, or `None` if either body has no shape
+    /// or both ends are on the same body.
+    ///
+    /// Anchors are local to their body, see [`Shape::local_point`] to get one
+    /// from a world position. Joints work between any mix of dynamic and
+    /// kinematic bodies, or pin a body to a point in the world.
+    pub fn add_joint(&mut self, joint: Joint) -> Option<JointId> {
+        self.collision_space.add_joint(joint)
+    }
+
+    /// Removes a joint, waking the bodies it held.
+    pub fn remove_joint(&mut self, id: JointId) -> Option<Joint> {
+        self.collision_space.remove_joint(id)
+    }
+
+    pub fn joint(&self, id: JointId) -> Option<&Joint> {
+        self.collision_space.joint(id)
+    }
+
+    pub fn joint_mut(&mut self, id: JointId) -> Option<&mut Joint> {
+        self.collision_space.joint_mut(id)
+    }
+
+    pub fn joints(&self) -> impl Iterator<Item = (JointId, &Joint)> {
+        self.collision_space.joints()
+    }
+
+    /// Sets the speed of a joint's motor, waking the bodies it drives: degrees
+    /// per second for turning joints, px/s for prismatic ones. Does nothing for
+    /// joints without a motor.
+    ///
+    /// Positive speeds turn body B in +rotation (clockwise on screen) relative to
+    /// body A. For a body pinned to the world they turn it anticlockwise, see
+    /// [`Joint::revolute`].
+    pub fn set_motor_speed(&mut self, id: JointId, speed: f32) {
+        self.collision_space.set_motor_speed(id, speed);
+    }
+
+    /// What happened to joints during the last [`World::step`], like joints
+    /// breaking when pulled past their
+    /// [`break_force`](crate::joints::Joint::break_force) or
+    /// [`break_torque`](crate::joints::Joint::break_torque).
+    ///
+    /// A broken joint has already been removed; its event carries the joint.
+    pub fn joint_events(&self) -> &[JointEvent] {
+        self.collision_space.joint_events()
+    }
+
+    /// World positions of both ends of a joint, for drawing it.
+    pub fn joint_anchors(&self, id: JointId) -> Option<((f32, f32), (f32, f32))> {
+        self.collision_space.joint_anchors(id)
+    }
+
+    pub fn thing(&self, id: Id) -> Option<&Thing> {
+        self.space.things.get(&id)
+    }
+
+    pub fn shape(&self, id: Id) -> Option<&Shape> {
+        self.collision_space.shapes.get(&id)
+    }
+
+    pub fn shape_mut(&mut self, id: Id) -> Option<&mut Shape> {
+        self.collision_space.shapes.get_mut(&id)
+    }
+
+    /// All registered things, including visual-only ones.
+    pub fn things(&self) -> impl Iterator<Item = &Thing> {
+        self.space.things.values()
+    }
+
+    /// Things paired with their shape in the collision space.
+    pub fn bodies(&self) -> impl Iterator<Item = (&Thing, &Shape)> {
+        let things = &self.space.things;
+        self.collision_space.shapes.iter()
+            .filter_map(move |(id, shape)| things.get(id).map(|thing| (thing, shape)))
+    }
+
+    pub fn bodies_mut(&mut self) -> impl Iterator<Item = (&Thing, &mut Shape)> {
+        let things = &self.space.things;
+        self.collision_space.shapes.iter_mut()
+            .filter_map(move |(id, shape)| things.get(id).map(|thing| (thing, shape)))
+    }
+
+    /// Things without a shape, paired with where they're drawn. They never move
+    /// on their own; change the transform through [`World::visual_mut`].
+    pub fn visuals(&self) -> impl Iterator<Item = (&Thing, &Transform)> {
+        let things = &self.space.things;
+        self.space.visuals.iter()
+            .filter_map(move |(id, transform)| things.get(id).map(|thing| (thing, transform)))
+    }
+
+    /// Where a thing without a shape is drawn, `None` for things with one.
+    pub fn visual(&self, id: Id) -> Option<&Transform> {
+        self.space.visuals.get(&id)
+    }
+
+    pub fn visual_mut(&mut self, id: Id) -> Option<&mut Transform> {
+        self.space.visuals.get_mut(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.space.things.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.space.things.is_empty()
+    }
+
+    pub fn collision_space(&self) -> &CollisionSpace {
+        &self.collision_space
+    }
+
+    pub fn collision_space_mut(&mut self) -> &mut CollisionSpace {
+        &mut self.collision_space
+    }
+
+    pub fn space(&self) -> &Space {
+        &self.space
+    }
+
+    /// Current state of every body in the collision space, ordered by id.
+    pub fn snapshot(&self) -> Vec<BodyState> {
+        let mut states: Vec<BodyState> = self.collision_space.shapes.iter()
+            .map(|(id, shape)| BodyState {
+                id: *id,
+                x_pos: shape.transform.x_pos,
+                y_pos: shape.transform.y_pos,
+                rotation: shape.transform.rotation,
+                vel_x: shape.transform.vel_x,
+                vel_y: shape.transform.vel_y,
+                angular_vel: shape.transform.angular_vel,
+                sleeping: shape.sleeping,
+            })
+            .collect();
+
+        states.sort_by_key(|state| state.id);
+        states
+    }
+}
+
+/// Position and velocity of a body at one point in time.
+#[derive(Debug, Clone, Serialize)]
+pub struct BodyState {
+    pub id: Id,
+    pub x_pos: f32,
+    pub y_pos: f32,
+    pub rotation: f32,
+    pub vel_x: f32,
+    pub vel_y: f32,
+    pub angular_vel: f32,
+    pub sleeping: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::collision::FIXED_TIMESTEP;
+    use crate::math::{length, sub};
+
+    // A floor with three boxes stacked on it, the boxes' ids from the bottom up
+    fn box_stack(world: &mut World) -> Vec<Id> {
+        world.register(
+            Transform::new(400.0, 500.0, 800, 40, 0.0),
+            Sprite::Rectangle,
+            None,
+            Some(Dynamics::Kinematic),
+            0.0,
+        ).unwrap();
+
+        (0..3)
+            .map(|level| {
+                world.register(
+                    Transform::new(400.0, 455.0 - level as f32 * 50.0, 50, 50, 0.0),
+                    Sprite::Rectangle,
+                    None,
+                    Some(Dynamics::Dynamic),
+                    0.0,
+                ).unwrap()
+            })
+            .collect()
+    }
+
+    fn all_asleep(world: &World, ids: &[Id]) -> bool {
+        ids.iter().all(|id| world.shape(*id).unwrap().sleeping)
+    }
+
+    #[test]
+    fn stack_sleeps_under_noisy_tilt() {
+        let mut world = World::new();
+        world.set_gravity_mode(GravityMode::Tilt);
+        let boxes = box_stack(&mut world);
+
+        // The Pico lying flat, its x reading flickering by one either way
+        let readings = [
+            Input::from_csv_line("1,0,256").unwrap(),
+            Input::from_csv_line("-1,0,256").unwrap(),
+        ];
+        for step in 0..180 {
+            world.apply_input(&readings[step % 2]);
+            world.step(FIXED_TIMESTEP);
+        }
+        assert!(all_asleep(&world, &boxes));
+
+        // A real tilt still wakes them
+        world.apply_input(&Input::from_csv_line("64,0,256").unwrap());
+        assert!(boxes.iter().all(|id| !world.shape(*id).unwrap().sleeping));
+    }
+
+    #[test]
+    fn ccd_bullet_stops_at_thin_wall() {
+        let mut world = World::new();
+        world.set_gravity(0.0, 0.0);
+        world.register(
+            Transform::new(400.0, 300.0, 4, 200, 0.0),
+            Sprite::Rectangle,
+            None,
+            Some(Dynamics::Kinematic),
+            0.0,
+        ).unwrap();
+        let bullet = world.register(
+            Transform::new(100.0, 300.0, 10, 10, 0.0),
+            Sprite::Circle,
+            None,
+            Some(Dynamics::Dynamic),
+            0.0,
+        ).unwrap();
+
+        // 75 px a substep, far more than the wall is thick
+        let shape = world.shape_mut(bullet).unwrap();
+        shape.ccd = true;
+        shape.set_velocity(9000.0, 0.0, 0.0);
+        for _ in 0..30 {
+            world.step(FIXED_TIMESTEP);
+        }
+
+        assert!(world.shape(bullet).unwrap().transform.x_pos < 400.0);
+    }
+
+    #[test]
+    fn box_stack_settles_without_drift() {
+        let mut world = World::new();
+        let boxes = box_stack(&mut world);
+
+        for _ in 0..300 {
+            world.step(FIXED_TIMESTEP);
+        }
+
+        assert!(all_asleep(&world, &boxes));
+        for (level, id) in boxes.iter().enumerate() {
+            let transform = &world.shape(*id).unwrap().transform;
+            let resting_y = 455.0 - level as f32 * 50.0;
+            assert!((transform.x_pos - 400.0).abs() < 0.5, "box {} drifted to x {}", level, transform.x_pos);
+            assert!((transform.y_pos - resting_y).abs() < 1.0, "box {} at y {}", level, transform.y_pos);
+            assert!(transform.rotation.abs() < 0.5, "box {} turned {}", level, transform.rotation);
+        }
+    }
+
+    // A post with a 200 px plank hinged to it by the plank's left end, the
+    // plank sticking out to the right
+    fn hinged_plank(world: &mut World) -> (Id, Id, Joint) {
+        let post = world.register(
+            Transform::new(300.0, 300.0, 20, 20, 0.0),
+            Sprite::Rectangle,
+            None,
+            Some(Dynamics::Kinematic),
+            0.0,
+        ).unwrap();
+        let plank = world.register(
+            Transform::new(400.0, 300.0, 200, 10, 0.0),
+            Sprite::Rectangle,
+            None,
+            Some(Dynamics::Dynamic),
+            0.0,
+        ).unwrap();
+
+        (post, plank, Joint::revolute(post, (0.0, 0.0), Some(plank), (-100.0, 0.0)))
+    }
+
+    #[test]
+    fn revolute_motor_turns_b_in_positive_rotation() {
+        let mut world = World::new();
+        world.set_gravity(0.0, 0.0);
+        let (_, plank, mut hinge) = hinged_plank(&mut world);
+        hinge.set_motor(90.0, 1.0e9);
+        world.add_joint(hinge).unwrap();
+
+        for _ in 0..30 {
+            world.step(FIXED_TIMESTEP);
+        }
+
+        // Half a second at 90 degrees/s, clockwise on screen
+        let rotation = world.shape(plank).unwrap().transform.rotation;
+        assert!((rotation - 45.0).abs() < 2.0, "rotation {}", rotation);
+    }
+
+    #[test]
+    fn revolute_motor_against_the_world_turns_a_the_other_way() {
+        let mut world = World::new();
+        world.set_gravity(0.0, 0.0);
+        let plank = world.register(
+            Transform::new(400.0, 300.0, 200, 10, 0.0),
+            Sprite::Rectangle,
+            None,
+            Some(Dynamics::Dynamic),
+            0.0,
+        ).unwrap();
+        let mut pin = Joint::revolute(plank, (-100.0, 0.0), None, (300.0, 300.0));
+        pin.set_motor(90.0, 1.0e9);
+        world.add_joint(pin).unwrap();
+
+        for _ in 0..30 {
+            world.step(FIXED_TIMESTEP);
+        }
+
+        let rotation = world.shape(plank).unwrap().transform.rotation;
+        assert!((rotation + 45.0).abs() < 2.0, "rotation {}", rotation);
+    }
+
+    #[test]
+    fn revolute_limit_holds_a_falling_plank() {
+        let mut world = World::new();
+        let (_, plank, mut hinge) = hinged_plank(&mut world);
+        hinge.set_limits(-10.0, 30.0);
+        let hinge = world.add_joint(hinge).unwrap();
+
+        // Gravity swings the free end down, towards positive rotation
+        for _ in 0..120 {
+            world.step(FIXED_TIMESTEP);
+            let rotation = world.shape(plank).unwrap().transform.rotation;
+            assert!(rotation < 31.0, "swung past the limit to {}", rotation);
+        }
+
+        let rotation = world.shape(plank).unwrap().transform.rotation;
+        assert!((rotation - 30.0).abs() < 1.0, "rotation {}", rotation);
+        let (pivot_a, pivot_b) = world.joint_anchors(hinge).unwrap();
+        assert!(length(sub(pivot_a, pivot_b)) < 1.0);
+    }
+
+    // A box welded by its centre to a point in the world, breaking under this
+    // many times its own weight. Returns the box and the weld.
+    fn welded_box(world: &mut World, strength: f32) -> (Id, JointId) {
+        let block = world.register(
+            Transform::new(400.0, 300.0, 20, 20, 0.0),
+            Sprite::Rectangle,
+            None,
+            Some(Dynamics::Dynamic),
+            0.0,
+        ).unwrap();
+        let weight = world.shape(block).unwrap().mass * world.gravity().1;
+
+        let mut weld = Joint::weld(block, (0.0, 0.0), None, (400.0, 300.0), 0.0);
+        weld.break_force = Some(weight * strength);
+        (block, world.add_joint(weld).unwrap())
+    }
+
+    #[test]
+    fn weld_breaks_when_it_cant_hold_the_weight() {
+        let mut world = World::new();
+        let (block, weld) = welded_box(&mut world, 0.9);
+
+        world.step(FIXED_TIMESTEP);
+
+        assert!(world.joint(weld).is_none());
+        match world.joint_events() {
+            [JointEvent::Broken { id, joint }] => {
+                assert_eq!(*id, weld);
+                assert_eq!(joint.body_a, block);
+            }
+            events => panic!("expected one broken weld, got {:?}", events),
+        }
+
+        // Events only last until the next step
+        world.step(FIXED_TIMESTEP);
+        assert!(world.joint_events().is_empty());
+    }
+
+    #[test]
+    fn weld_holds_just_over_the_weight() {
+        let mut world = World::new();
+        let (block, weld) = welded_box(&mut world, 1.1);
+
+        for _ in 0..60 {
+            world.step(FIXED_TIMESTEP);
+        }
+
+        assert!(world.joint(weld).is_some());
+        assert!(world.joint_events().is_empty());
+        assert!((world.shape(block).unwrap().transform.y_pos - 300.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn visual_only_things_stay_where_they_were_put() {
+        let mut world = World::new();
+        let without = world.register(Transform::new(120.0, 80.0, 40, 20, 30.0), Sprite::Rectangle, None, None, 0.0).unwrap();
+        let none = world.register(Transform::new(300.0, 90.0, 20, 20, 0.0), Sprite::Circle, None, Some(Dynamics::None), 0.0).unwrap();
+
+        for _ in 0..60 {
+            world.step(FIXED_TIMESTEP);
+        }
+
+        for (id, (x_pos, y_pos, rotation)) in [(without, (120.0, 80.0, 30.0)), (none, (300.0, 90.0, 0.0))] {
+            assert!(world.shape(id).is_none());
+            let transform = world.visual(id).unwrap();
+            assert_eq!((transform.x_pos, transform.y_pos, transform.rotation), (x_pos, y_pos, rotation));
+        }
+        assert_eq!(world.visuals().count(), 2);
+    }
+
+    // A frictionless flat floor at y 500, made of 50 px chain segments
+    fn chain_floor(world: &mut World) -> Id {
+        let points = (0..=16).map(|i| (i as f32 * 50.0, 500.0)).collect();
+        let floor = world.register(Transform::new(0.0, 0.0, 0, 0, 0.0), Sprite::Chain(points), None, Some(Dynamics::Kinematic), 0.0).unwrap();
+        world.shape_mut(floor).unwrap().set_friction(0.0, 0.0);
+        floor
+    }
+
+    #[test]
+    fn shapes_slide_over_chain_joints_without_catching() {
+        for (sprite, size) in [(Sprite::Rectangle, 40), (Sprite::Circle, 30)] {
+            let mut world = World::new();
+            chain_floor(&mut world);
+            let slider = world.register(
+                Transform::new(60.0, 500.0 - size as f32 / 2.0, size, size, 0.0),
+                sprite,
+                None,
+                Some(Dynamics::Dynamic),
+                0.0,
+            ).unwrap();
+            world.shape_mut(slider).unwrap().set_velocity(300.0, 0.0, 0.0);
+
+            // Two seconds crosses a dozen joints between segments
+            for _ in 0..120 {
+                world.step(FIXED_TIMESTEP);
+                let transform = &world.shape(slider).unwrap().transform;
+                assert!((transform.vel_x - 300.0).abs() < 1.0, "caught on a joint, vel_x {}", transform.vel_x);
+                assert!(transform.vel_y > -1.0, "bumped up, vel_y {}", transform.vel_y);
+            }
+
+            let transform = &world.shape(slider).unwrap().transform;
+            assert!((transform.x_pos - 660.0).abs() < 2.0, "x {}", transform.x_pos);
+        }
+    }
+
+    #[test]
+    fn ball_stays_inside_a_closed_chain() {
+        let mut world = World::new();
+        let corners = vec![(200.0, 200.0), (400.0, 200.0), (400.0, 400.0), (200.0, 400.0), (200.0, 200.0)];
+        world.register(Transform::new(0.0, 0.0, 0, 0, 0.0), Sprite::Chain(corners), None, Some(Dynamics::Kinematic), 0.0).unwrap();
+        let ball = world.register(Transform::new(300.0, 300.0, 30, 30, 0.0), Sprite::Circle, None, Some(Dynamics::Dynamic), 0.0).unwrap();
+        world.shape_mut(ball).unwrap().set_velocity(800.0, -600.0, 0.0);
+
+        for _ in 0..300 {
+            world.step(FIXED_TIMESTEP);
+            let transform = &world.shape(ball).unwrap().transform;
+            // Hits may sink it in a little, but never past half its radius
+            let inside = 200.0 + 7.5..=400.0 - 7.5;
+            assert!(
+                inside.contains(&transform.x_pos) && inside.contains(&transform.y_pos),
+                "escaped to ({}, {})", transform.x_pos, transform.y_pos,
+            );
+        }
+
+        let shape = world.shape(ball).unwrap();
+        assert!(shape.sleeping);
+        assert!((shape.transform.y_pos - 385.0).abs() < 1.0, "resting at y {}", shape.transform.y_pos);
+    }
+
+    #[test]
+    fn joints_need_two_different_bodies() {
+        let mut world = World::new();
+        let (post, plank, _) = hinged_plank(&mut world);
+
+        assert!(world.add_joint(Joint::distance(plank, (0.0, 0.0), Some(plank), (50.0, 0.0), 50.0)).is_none());
+        assert!(world.add_joint(Joint::distance(plank, (0.0, 0.0), Some(Id::MAX), (0.0, 0.0), 50.0)).is_none());
+        assert!(world.add_joint(Joint::distance(plank, (0.0, 0.0), Some(post), (0.0, 0.0), 100.0)).is_some());
+        assert_eq!(world.joints().count(), 1);
+    }
+
+    #[test]
+    fn pendulum_keeps_its_length() {
+        let mut world = World::new();
+        let bob = world.register(Transform::new(500.0, 200.0, 20, 20, 0.0), Sprite::Circle, None, Some(Dynamics::Dynamic), 0.0).unwrap();
+        // Starts level with the pivot, so it swings through the bottom as fast as it gets
+        let rod = world.add_joint(Joint::distance(bob, (0.0, 0.0), None, (300.0, 200.0), 200.0)).unwrap();
+
+        let mut lowest: f32 = 0.0;
+        for _ in 0..180 {
+            world.step(FIXED_TIMESTEP);
+            let (end, pivot) = world.joint_anchors(rod).unwrap();
+            let stretch = length(sub(end, pivot)) - 200.0;
+            assert!(stretch.abs() < 2.0, "rod length off by {}", stretch);
+            lowest = lowest.max(end.1);
+        }
+
+        assert!(lowest > 395.0, "never swung down, lowest y {}", lowest);
+    }
+
+    #[test]
+    fn rope_goes_slack_but_never_stretches() {
+        let mut world = World::new();
+        // Dropped from right under the anchor, 50 px of slack before the rope catches it
+        let weight = world.register(Transform::new(300.0, 250.0, 20, 20, 0.0), Sprite::Circle, None, Some(Dynamics::Dynamic), 0.0).unwrap();
+        let rope = world.add_joint(Joint::rope(weight, (0.0, 0.0), None, (300.0, 200.0), 100.0)).unwrap();
+
+        let mut slack_steps = 0;
+        for _ in 0..120 {
+            world.step(FIXED_TIMESTEP);
+            let (end, anchor) = world.joint_anchors(rope).unwrap();
+            let distance = length(sub(end, anchor));
+            assert!(distance < 102.0, "rope stretched to {}", distance);
+            if distance < 99.0 {
+                slack_steps += 1;
+            }
+        }
+
+        // Fell freely at first, then hangs at full length
+        assert!(slack_steps > 0);
+        let (end, anchor) = world.joint_anchors(rope).unwrap();
+        assert!((length(sub(end, anchor)) - 100.0).abs() < 1.0);
+    }
+
+    // Positions (x, relative to the spring's rest point) of a 1 mass weight on a
+    // spring, let go 50 px out, after each step for four seconds
+    fn spring_swing(stiffness: f32, damping: f32) -> Vec<f32> {
+        let mut world = World::new();
+        world.set_gravity(0.0, 0.0);
+        let weight = world.register(Transform::new(350.0, 300.0, 20, 20, 0.0), Sprite::Circle, None, Some(Dynamics::Dynamic), 0.0).unwrap();
+        world.shape_mut(weight).unwrap().set_mass(1.0);
+        world.add_joint(Joint::spring(weight, (0.0, 0.0), None, (200.0, 300.0), 100.0, stiffness, damping)).unwrap();
+
+        (0..240)
+            .map(|_| {
+                world.step(FIXED_TIMESTEP);
+                world.shape(weight).unwrap().transform.x_pos - 300.0
+            })
+            .collect()
+    }
+
+    // Times (seconds) and heights of the swing's peaks
+    fn peaks(positions: &[f32]) -> Vec<(f32, f32)> {
+        positions.windows(3).enumerate()
+            .filter(|(_, w)| w[1] > w[0] && w[1] >= w[2])
+            .map(|(i, w)| ((i + 1) as f32 * FIXED_TIMESTEP, w[1]))
+            .collect()
+    }
+
+    #[test]
+    fn spring_swings_at_its_frequency_and_damps_down() {
+        // 40 force per px on a mass of 1, a period of 2 pi / sqrt(40) seconds
+        let period = 2.0 * std::f32::consts::PI / 40.0_f32.sqrt();
+        let light = peaks(&spring_swing(40.0, 0.4));
+        let heavy = peaks(&spring_swing(40.0, 2.0));
+
+        let (first, last) = (light[0], light[light.len() - 1]);
+        let measured = (last.0 - first.0) / (light.len() - 1) as f32;
+        assert!((measured - period).abs() < 0.03 * period, "period {}, expected {}", measured, period);
+
+        // Every swing smaller than the last, and more damping shrinks them faster.
+        // The solver adds a little damping of its own, so no exact rate.
+        for peaks in [&light, &heavy] {
+            assert!(peaks.windows(2).all(|pair| pair[1].1 < pair[0].1), "not decaying: {:?}", peaks);
+        }
+        assert!(heavy[1].1 / heavy[0].1 < light[1].1 / light[0].1);
+    }
+
+    // A kinematic rail at (300, 300) and a 20 px block of mass 1 on it, joined
+    // anchor to centre
+    fn rail_and_block(world: &mut World) -> (Id, Id) {
+        let rail = world.register(Transform::new(300.0, 300.0, 10, 10, 0.0), Sprite::Rectangle, None, Some(Dynamics::Kinematic), 0.0).unwrap();
+        let block = world.register(Transform::new(300.0, 300.0, 20, 20, 0.0), Sprite::Rectangle, None, Some(Dynamics::Dynamic), 0.0).unwrap();
+        world.shape_mut(block).unwrap().set_mass(1.0);
+        (rail, block)
+    }
+
+    #[test]
+    fn prismatic_motor_slides_b_along_the_axis() {
+        let mut world = World::new();
+        world.set_gravity(0.0, 0.0);
+        let (rail, block) = rail_and_block(&mut world);
+        let mut slider = Joint::prismatic(rail, (0.0, 0.0), Some(block), (0.0, 0.0), (1.0, 0.0));
+        slider.set_motor(100.0, 1.0e6);
+        world.add_joint(slider).unwrap();
+
+        for _ in 0..30 {
+            world.step(FIXED_TIMESTEP);
+        }
+
+        // Half a second at 100 px/s, without drifting across or turning
+        let transform = &world.shape(block).unwrap().transform;
+        assert!((transform.x_pos - 350.0).abs() < 1.0, "x {}", transform.x_pos);
+        assert!((transform.y_pos - 300.0).abs() < 0.5, "y {}", transform.y_pos);
+        assert!(transform.rotation.abs() < 0.5, "rotation {}", transform.rotation);
+    }
+
+    #[test]
+    fn prismatic_limit_holds_a_falling_block() {
+        let mut world = World::new();
+        let (rail, block) = rail_and_block(&mut world);
+        // Straight down the screen, with gravity
+        let mut slider = Joint::prismatic(rail, (0.0, 0.0), Some(block), (0.0, 0.0), (0.0, 1.0));
+        slider.set_limits(-20.0, 40.0);
+        let slider = world.add_joint(slider).unwrap();
+
+        for _ in 0..120 {
+            world.step(FIXED_TIMESTEP);
+            let y_pos = world.shape(block).unwrap().transform.y_pos;
+            assert!(y_pos < 341.0, "slid past the limit to {}", y_pos);
+        }
+
+        let y_pos = world.shape(block).unwrap().transform.y_pos;
+        assert!((y_pos - 340.0).abs() < 1.0, "y {}", y_pos);
+        // The upper limit holds the block's weight back, against the axis
+        let substep_dt = FIXED_TIMESTEP / 2.0;
+        let impulse = world.joint(slider).unwrap().impulse();
+        assert!((impulse + world.gravity().1 * substep_dt).abs() < 0.05 * world.gravity().1 * substep_dt, "impulse {}", impulse);
+    }
+
+    #[test]
+    fn wheel_suspension_settles_at_its_rest_offset_under_load() {
+        let mut world = World::new();
+        let (chassis, wheel) = rail_and_block(&mut world);
+        // Sags 10 px under the wheel's weight, about critically damped
+        let stiffness = world.gravity().1 / 10.0;
+        let damping = 2.0 * stiffness.sqrt();
+        world.add_joint(Joint::wheel(chassis, (0.0, 0.0), wheel, (0.0, 0.0), (0.0, 1.0), stiffness, damping)).unwrap();
+
+        for step in 0..180 {
+            world.step(FIXED_TIMESTEP);
+            let transform = &world.shape(wheel).unwrap().transform;
+            assert!((transform.x_pos - 300.0).abs() < 0.5, "wheel drifted sideways to {}", transform.x_pos);
+            if step >= 60 {
+                assert!((transform.y_pos - 310.0).abs() < 1.0, "y {} at step {}", transform.y_pos, step);
+            }
+        }
+    }
+}
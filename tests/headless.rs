@@ -0,0 +1,68 @@
+// Runs checked-in scenes through the headless runner and compares the final
+// body states against golden files. After a change that's meant to move things,
+// regenerate a golden file with:
+//
+//   cargo run --no-default-features --bin headless -- tests/scenes/pile.json --steps 300 --out tests/golden/pile.json
+
+use std::process::Command;
+
+use serde_json::Value;
+
+const POSITION_TOLERANCE: f64 = 0.5; // px
+const ROTATION_TOLERANCE: f64 = 1.0; // Degrees
+const VELOCITY_TOLERANCE: f64 = 1.0; // px/s, or degrees/s
+
+fn run_headless(scene: &str, steps: u32, extra_args: &[&str]) -> Vec<Value> {
+    let output = Command::new(env!("CARGO_BIN_EXE_headless"))
+        .args([scene, "--steps", &steps.to_string()])
+        .args(extra_args)
+        .output()
+        .expect("headless runner should start");
+    assert!(output.status.success(), "headless failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    serde_json::from_slice(&output.stdout).expect("headless should print body states as JSON")
+}
+
+fn load_golden(path: &str) -> Vec<Value> {
+    let contents = std::fs::read_to_string(path).expect("golden file should exist");
+    serde_json::from_str(&contents).expect("golden file should be JSON")
+}
+
+fn assert_matches_golden(states: &[Value], golden: &[Value]) {
+    assert_eq!(states.len(), golden.len(), "different number of bodies");
+
+    for (state, expected) in states.iter().zip(golden) {
+        let id = &expected["id"];
+        assert_eq!(&state["id"], id);
+        assert_eq!(state["sleeping"], expected["sleeping"], "body {} sleeping", id);
+
+        for (field, tolerance) in [
+            ("x_pos", POSITION_TOLERANCE),
+            ("y_pos", POSITION_TOLERANCE),
+            ("rotation", ROTATION_TOLERANCE),
+            ("vel_x", VELOCITY_TOLERANCE),
+            ("vel_y", VELOCITY_TOLERANCE),
+            ("angular_vel", VELOCITY_TOLERANCE),
+        ] {
+            let actual = state[field].as_f64().unwrap();
+            let wanted = expected[field].as_f64().unwrap();
+            assert!(
+                (actual - wanted).abs() <= tolerance,
+                "body {} {}: got {}, expected {}", id, field, actual, wanted,
+            );
+        }
+    }
+}
+
+#[test]
+fn pile_matches_golden() {
+    let states = run_headless("tests/scenes/pile.json", 300, &[]);
+    assert_matches_golden(&states, &load_golden("tests/golden/pile.json"));
+}
+
+// A level controller adds no tilt, so the walls and the ramp stay as authored
+#[test]
+fn level_input_leaves_kinematic_bodies_as_authored() {
+    let states = run_headless("tests/scenes/pile.json", 300, &["--input", "tests/scenes/level.csv"]);
+    assert_matches_golden(&states, &load_golden("tests/golden/pile.json"));
+}